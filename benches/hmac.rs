@@ -0,0 +1,49 @@
+//! Compare HMAC verification with a precomputed key state against key setup for every message.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use crypto_mac::{Mac, NewMac};
+use jws::{json_object, JsonObject, Verifier};
+use jws::compact::encode_sign;
+use jws::hmac::{HmacVerifier, Hs256Signer};
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+const KEY : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+
+/// HS256 verifier that sets up the HMAC key for every message, like `HmacVerifier` did before key states were precomputed.
+struct PerMessageKeySetup<'a> {
+	key: &'a [u8],
+}
+
+impl Verifier for PerMessageKeySetup<'_> {
+	fn verify(&self, _protected_header: Option<&JsonObject>, _unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> jws::Result<()> {
+		let mut mac = HmacSha256::new_varkey(self.key).unwrap();
+		mac.reset();
+		mac.update(encoded_header);
+		mac.update(b".");
+		mac.update(encoded_payload);
+		mac.verify(signature).map_err(|_| jws::Error::invalid_signature(""))
+	}
+}
+
+fn verify_hs256(c: &mut Criterion) {
+	let claims  = br#"{"sub":"1234567890","name":"John Doe","iat":1516239022}"#;
+	let signed  = encode_sign(json_object!{"typ": "JWT"}, claims, &Hs256Signer::new(KEY).unwrap()).unwrap();
+	let header  = json_object!{"alg": "HS256", "typ": "JWT"};
+	let signature = base64::decode_config(signed.signature(), base64::URL_SAFE_NO_PAD).unwrap();
+
+	let precomputed = HmacVerifier::new(KEY).unwrap();
+	let per_message = PerMessageKeySetup{key: KEY};
+
+	let mut group = c.benchmark_group("verify HS256");
+	group.bench_function("precomputed key state", |b| b.iter(|| {
+		precomputed.verify(Some(&header), None, signed.header().as_bytes(), signed.payload().as_bytes(), black_box(&signature)).unwrap()
+	}));
+	group.bench_function("key setup per message", |b| b.iter(|| {
+		per_message.verify(Some(&header), None, signed.header().as_bytes(), signed.payload().as_bytes(), black_box(&signature)).unwrap()
+	}));
+	group.finish();
+}
+
+criterion_group!(benches, verify_hs256);
+criterion_main!(benches);
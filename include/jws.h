@@ -0,0 +1,146 @@
+/* C API of the jws library, generated with cbindgen from src/ffi.rs. Do not edit. */
+
+#ifndef JWS_H
+#define JWS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Status code returned by all functions of the C API.
+//
+// The error codes correspond to the variants of [`ErrorKind`].
+// The numeric values are part of the ABI and will not change.
+typedef enum JwsStatus {
+  // The function succeeded.
+  JWS_STATUS_OK = 0,
+  // A required header parameter is missing.
+  JWS_STATUS_MISSING_HEADER_PARAM = 1,
+  // A header parameter was found but its value is invalid.
+  JWS_STATUS_INVALID_HEADER_PARAM = 2,
+  // The MAC algorithm indicated by the message header or requested for a key is not supported.
+  JWS_STATUS_UNSUPPORTED_MAC_ALGORITHM = 3,
+  // The message being processed is not valid.
+  JWS_STATUS_INVALID_MESSAGE = 4,
+  // The signature of the message being verified is invalid.
+  JWS_STATUS_INVALID_SIGNATURE = 5,
+  // The encryption algorithm is not supported.
+  JWS_STATUS_UNSUPPORTED_ENCRYPTION_ALGORITHM = 6,
+  // The content of an encrypted message could not be decrypted.
+  JWS_STATUS_DECRYPTION_FAILED = 7,
+  // A key is not suitable for the algorithm, for example because it is too short.
+  JWS_STATUS_INVALID_KEY = 8,
+  // An error that doesn't match any of the other types.
+  JWS_STATUS_OTHER = 9,
+  // A required pointer argument is null or a string argument is not valid UTF-8.
+  JWS_STATUS_INVALID_ARGUMENT = 10,
+  // The library panicked.
+  JWS_STATUS_PANIC = 11,
+} JwsStatus;
+
+// Opaque handle to a key that can be used to sign and verify messages.
+//
+// Create a key with [`jws_key_new_hmac`] and release it with [`jws_key_free`].
+// The key material is zeroized when the key is released.
+typedef struct JwsKey JwsKey;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create a new HMAC key.
+//
+// The algorithm must be one of `HS256`, `HS384` or `HS512`.
+// It determines the algorithm used for signing.
+// Verification accepts all three algorithms, as indicated by the `alg` header parameter of the message.
+//
+// On success, the key handle is written to `out_key`.
+//
+// # Safety
+// `algorithm` must be a valid nul-terminated string, `key` must point to `key_len` readable bytes
+// and `out_key` must be valid for writes.
+enum JwsStatus jws_key_new_hmac(const char *algorithm,
+                                const uint8_t *key,
+                                size_t key_len,
+                                struct JwsKey **out_key);
+
+// Release a key created with [`jws_key_new_hmac`].
+//
+// Passing a null pointer is allowed and does nothing.
+//
+// # Safety
+// `key` must be null or a key handle that has not been released yet.
+void jws_key_free(struct JwsKey *key);
+
+// Sign a payload and encode it using the JWS Compact Serialization format.
+//
+// `header_json` may be null or a JSON object with additional header parameters.
+// On success, the encoded message is written to `out_token` and must be released with [`jws_string_free`].
+//
+// # Safety
+// `key` must be a valid key handle, `header_json` must be null or a valid nul-terminated string,
+// `payload` must point to `payload_len` readable bytes and `out_token` must be valid for writes.
+enum JwsStatus jws_sign_compact(const struct JwsKey *key,
+                                const char *header_json,
+                                const uint8_t *payload,
+                                size_t payload_len,
+                                char **out_token);
+
+// Decode a message in the JWS Compact Serialization format and verify the signature.
+//
+// On success, the header is written to `out_header_json` as JSON object and the payload is written to `out_payload` and `out_payload_len`.
+// Each output pointer may be null if the value is not needed,
+// except that `out_payload_len` is required when `out_payload` is given.
+// The returned header must be released with [`jws_string_free`] and the payload with [`jws_bytes_free`].
+//
+// # Safety
+// `key` must be a valid key handle, `token` must be a valid nul-terminated string
+// and the output pointers must be null or valid for writes.
+enum JwsStatus jws_verify_compact(const struct JwsKey *key,
+                                  const char *token,
+                                  char **out_header_json,
+                                  uint8_t **out_payload,
+                                  size_t *out_payload_len);
+
+// Decode a message in the JWS Compact Serialization format without verifying the signature.
+//
+// The outputs are the same as for [`jws_verify_compact`].
+// Do not trust the decoded message before it has been verified.
+//
+// # Safety
+// `token` must be a valid nul-terminated string and the output pointers must be null or valid for writes.
+enum JwsStatus jws_decode_unverified(const char *token,
+                                     char **out_header_json,
+                                     uint8_t **out_payload,
+                                     size_t *out_payload_len);
+
+// Get a description of the last error that occurred on the calling thread.
+//
+// Returns null if the last function call succeeded.
+// The returned string is owned by the library and remains valid until the next call to the library on the same thread.
+const char *jws_last_error_message(void);
+
+// Release a string returned by the library.
+//
+// Passing a null pointer is allowed and does nothing.
+//
+// # Safety
+// `string` must be null or a string returned by the library that has not been released yet.
+void jws_string_free(char *string);
+
+// Release a byte buffer returned by the library.
+//
+// Passing a null pointer is allowed and does nothing.
+//
+// # Safety
+// `data` must be null or a buffer returned by the library that has not been released yet,
+// and `len` must be the length that was returned with it.
+void jws_bytes_free(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* JWS_H */
@@ -0,0 +1,284 @@
+//! Signing through external key holders.
+//!
+//! A [`SigningBackend`] computes signatures with a key that is held elsewhere,
+//! like a PKCS#11 token, a cloud key management service or an agent process.
+//! The private key material never has to be exposed to the application.
+//!
+//! Wrap a backend in a [`BackendSigner`] to use it as [`Signer`] with [`crate::compact::encode_sign`].
+//! Backends that need to perform asynchronous operations can implement [`AsyncSigningBackend`] instead,
+//! and be wrapped in an [`AsyncBackendSigner`] to use it with [`crate::compact::encode_sign_async`].
+//!
+//! The [`SoftwareBackend`] holds a key in memory, which is useful for testing and local development.
+
+use core::future::Future;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{AsyncSigner, JsonObject, JsonValue, KeyMaterial, Result, Signer};
+use crate::key::Redacted;
+
+/// Information about the key used by a signing backend.
+pub trait BackendKey {
+	/// Get the JWS algorithm of the key, as used for the `alg` header parameter.
+	fn algorithm(&self) -> &str;
+
+	/// Get the key ID, as used for the `kid` header parameter.
+	///
+	/// If this returns `None`, no `kid` header parameter is added.
+	fn key_id(&self) -> Option<&str> {
+		None
+	}
+}
+
+/// A backend that computes signatures with a key it holds.
+pub trait SigningBackend: BackendKey {
+	/// Sign the JWS signing input.
+	///
+	/// The signing input consists of the encoded protected header and the encoded payload, separated by a period.
+	/// The returned signature must be plain bytes in the format specified for the JWS algorithm, not hex or base64 encoded.
+	fn sign(&self, signing_input: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A backend that computes signatures asynchronously with a key it holds.
+///
+/// All synchronous [`SigningBackend`] implementations are also asynchronous signing backends.
+pub trait AsyncSigningBackend: BackendKey {
+	/// Sign the JWS signing input.
+	///
+	/// See [`SigningBackend::sign`].
+	fn sign_async(&self, signing_input: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send;
+}
+
+impl<B: SigningBackend + Sync> AsyncSigningBackend for B {
+	fn sign_async(&self, signing_input: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send {
+		core::future::ready(self.sign(signing_input))
+	}
+}
+
+/// A [`Signer`] that delegates signing to a [`SigningBackend`].
+///
+/// The signer sets the `alg` header parameter and, if the backend has a key ID, the `kid` header parameter.
+#[derive(Clone, Debug)]
+pub struct BackendSigner<Backend> {
+	backend: Backend,
+}
+
+/// An [`AsyncSigner`] that delegates signing to an [`AsyncSigningBackend`].
+///
+/// The signer sets the `alg` header parameter and, if the backend has a key ID, the `kid` header parameter.
+#[derive(Clone, Debug)]
+pub struct AsyncBackendSigner<Backend> {
+	backend: Backend,
+}
+
+impl<B> BackendSigner<B> {
+	/// Create a new signer for a backend.
+	pub fn new(backend: B) -> Self {
+		Self{backend}
+	}
+
+	/// Get a reference to the backend.
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
+	/// Get the backend, consuming the signer.
+	pub fn into_inner(self) -> B {
+		self.backend
+	}
+}
+
+impl<B> AsyncBackendSigner<B> {
+	/// Create a new asynchronous signer for a backend.
+	pub fn new(backend: B) -> Self {
+		Self{backend}
+	}
+
+	/// Get a reference to the backend.
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
+	/// Get the backend, consuming the signer.
+	pub fn into_inner(self) -> B {
+		self.backend
+	}
+}
+
+impl<B: SigningBackend> Signer for BackendSigner<B> {
+	fn set_header_params(&self, header: &mut JsonObject) {
+		set_header_params(&self.backend, header);
+	}
+
+	fn compute_mac(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>> {
+		self.backend.sign(&signing_input(encoded_header, encoded_payload))
+	}
+}
+
+impl<B: AsyncSigningBackend + Sync> AsyncSigner for AsyncBackendSigner<B> {
+	fn set_header_params_async(&self, header: &mut JsonObject) {
+		set_header_params(&self.backend, header);
+	}
+
+	fn compute_mac_async(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send {
+		let signing_input = signing_input(encoded_header, encoded_payload);
+		async move {
+			self.backend.sign_async(&signing_input).await
+		}
+	}
+}
+
+/// A signing backend that holds a HMAC key in memory.
+///
+/// Supports the HS256, HS384 and HS512 algorithms.
+/// Keys shorter than the hash output size are rejected when signing.
+/// This backend is mostly useful for testing and local development,
+/// in production the key should be held by an external key holder.
+/// An owned key is zeroized when the backend is dropped.
+#[derive(Clone)]
+pub struct SoftwareBackend<Key: KeyMaterial> {
+	algorithm : String,
+	key_id    : Option<String>,
+	key       : Key,
+}
+
+impl<K: KeyMaterial> SoftwareBackend<K> {
+	/// Create a new software backend for a HMAC algorithm.
+	///
+	/// An unsupported algorithm is reported when signing.
+	pub fn new(algorithm: impl Into<String>, key: K) -> Self {
+		Self{algorithm: algorithm.into(), key_id: None, key}
+	}
+
+	/// Set the key ID to put in the `kid` header parameter.
+	pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+		self.key_id = Some(key_id.into());
+		self
+	}
+}
+
+impl<K: KeyMaterial> BackendKey for SoftwareBackend<K> {
+	fn algorithm(&self) -> &str {
+		&self.algorithm
+	}
+
+	fn key_id(&self) -> Option<&str> {
+		self.key_id.as_deref()
+	}
+}
+
+impl<K: KeyMaterial> SigningBackend for SoftwareBackend<K> {
+	fn sign(&self, signing_input: &[u8]) -> Result<Vec<u8>> {
+		crate::hmac::compute_hmac(&self.algorithm, self.key.as_ref(), signing_input)
+	}
+}
+
+impl<K: KeyMaterial> Drop for SoftwareBackend<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> core::fmt::Debug for SoftwareBackend<K> {
+	fn fmt(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+		formatter.debug_struct("SoftwareBackend")
+			.field("algorithm", &self.algorithm)
+			.field("key_id", &self.key_id)
+			.field("key", &Redacted)
+			.finish()
+	}
+}
+
+/// Set the `alg` and `kid` header parameters for a backend key.
+fn set_header_params(key: &impl BackendKey, header: &mut JsonObject) {
+	header.insert("alg".to_string(), JsonValue::from(key.algorithm()));
+	if let Some(key_id) = key.key_id() {
+		header.insert("kid".to_string(), JsonValue::from(key_id));
+	}
+}
+
+/// Build the JWS signing input from the encoded header and payload.
+fn signing_input(encoded_header: &[u8], encoded_payload: &[u8]) -> Vec<u8> {
+	let mut signing_input = Vec::with_capacity(encoded_header.len() + encoded_payload.len() + 1);
+	signing_input.extend_from_slice(encoded_header);
+	signing_input.push(b'.');
+	signing_input.extend_from_slice(encoded_payload);
+	signing_input
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{compact, Error, json_object};
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+
+	use assert2::assert;
+	use std::sync::Mutex;
+
+	/// Backend that records the signing inputs and returns a fixed signature, like a remote key holder would.
+	#[derive(Debug, Default)]
+	struct MockBackend {
+		requests : Mutex<Vec<Vec<u8>>>,
+		fail     : bool,
+	}
+
+	impl BackendKey for MockBackend {
+		fn algorithm(&self) -> &str {
+			"ES256"
+		}
+
+		fn key_id(&self) -> Option<&str> {
+			Some("kms-key-1")
+		}
+	}
+
+	impl AsyncSigningBackend for MockBackend {
+		async fn sign_async(&self, signing_input: &[u8]) -> Result<Vec<u8>> {
+			self.requests.lock().unwrap().push(signing_input.to_vec());
+			if self.fail {
+				Err(Error::other("key holder unavailable"))
+			} else {
+				Ok(b"signature".to_vec())
+			}
+		}
+	}
+
+	#[test]
+	fn test_software_backend() {
+		let signer  = BackendSigner::new(SoftwareBackend::new("HS256", b"this is a secret key that is long enough for all HMAC algorithms").with_key_id("local"));
+		let signed  = compact::encode_sign(json_object!{}, b"foo", &signer).unwrap();
+		let decoded = compact::decode_verify(signed.as_bytes(), &HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		assert!(decoded.header == json_object!{"alg": "HS256", "kid": "local"});
+
+		// The backend produces the same signature as the in-process signer.
+		let expected = compact::encode_sign(json_object!{"kid": "local"}, b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		assert!(signed == expected);
+
+		let signer = BackendSigner::new(SoftwareBackend::new("RS256", b"this is a secret key that is long enough for all HMAC algorithms"));
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::encode_sign(json_object!{}, b"foo", &signer));
+	}
+
+	#[test]
+	fn test_software_backend_async() {
+		let signer = AsyncBackendSigner::new(SoftwareBackend::new("HS512", b"this is a secret key that is long enough for all HMAC algorithms"));
+		let signed = futures::executor::block_on(compact::encode_sign_async(json_object!{}, b"foo", &signer)).unwrap();
+		assert!(let Ok(_) = compact::decode_verify(signed.as_bytes(), &HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()));
+	}
+
+	#[test]
+	fn test_mock_backend() {
+		let signer = AsyncBackendSigner::new(MockBackend::default());
+		let signed = futures::executor::block_on(compact::encode_sign_async(json_object!{}, b"foo", &signer)).unwrap();
+		let (decoded, signature) = compact::decode_unverified(signed.as_bytes()).unwrap();
+		assert!(decoded.header == json_object!{"alg": "ES256", "kid": "kms-key-1"});
+		assert!(signature == b"signature");
+
+		// The backend received the JWS signing input.
+		let signing_input = format!("{}.{}", signed.header(), signed.payload());
+		assert!(signer.backend().requests.lock().unwrap().as_slice() == &[signing_input.into_bytes()]);
+
+		let signer = AsyncBackendSigner::new(MockBackend{fail: true, ..MockBackend::default()});
+		assert!(let Err(Error { kind: Error::Other, .. }) = futures::executor::block_on(compact::encode_sign_async(json_object!{}, b"foo", &signer)));
+	}
+}
@@ -1,6 +1,10 @@
-///! Combine multiple verifiers.
+//! Combine multiple verifiers.
 
-use crate::{Error, JsonObject, Result, Verifier};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Error, JsonObject, MessagePart, MultiSigner, Result, Signer, Verifier};
 
 #[derive(Clone, Debug)]
 pub struct OrVerifier<Left, Right> {
@@ -14,6 +18,71 @@ pub struct AndVerifier<Left, Right> {
 	pub right : Right,
 }
 
+/// A set of verifiers of which at least `threshold` must have signed a message with multiple signatures.
+///
+/// Use it with [`crate::json::decode_verify_threshold`], which counts each verifier and each signature only once.
+/// It is deliberately not a [`Verifier`] itself: a message with a single signature can not prove that multiple keys signed it.
+///
+/// Each verifier counts as a distinct key, so the verifiers must not share keys.
+/// Verifiers with the same [`Verifier::label`] are rejected when the threshold verifier is created,
+/// but verifiers without a label can not be checked.
+#[derive(Clone, Debug)]
+pub struct ThresholdVerifier<V> {
+	threshold : usize,
+	verifiers : Vec<V>,
+}
+
+/// Signer that adds a signature for each of the wrapped signers.
+///
+/// This is only useful for serializations that support multiple signatures, like the [`crate::json`] module.
+#[derive(Clone, Debug)]
+pub struct AndSigner<Left, Right> {
+	pub left  : Left,
+	pub right : Right,
+}
+
+/// Diagnostic report with the outcome of every verifier that was tried.
+///
+/// A report is only produced when explicitly requested with [`Verifier::verify_report`] or [`crate::compact::decode_verify_report`].
+/// It contains the error of every rejecting verifier, so it should be logged rather than returned to untrusted callers.
+#[derive(Clone, Debug, Default)]
+pub struct VerificationReport {
+	/// The outcomes in the order the verifiers were tried.
+	pub outcomes: Vec<VerifierOutcome>,
+}
+
+/// The outcome of a single verifier in a [`VerificationReport`].
+#[derive(Clone, Debug)]
+pub struct VerifierOutcome {
+	/// The type name of the verifier.
+	pub verifier : &'static str,
+
+	/// The label of the key used by the verifier, if it has one.
+	///
+	/// See [`Verifier::label`].
+	pub key      : Option<String>,
+
+	/// The result of the verifier.
+	pub result   : Result<()>,
+}
+
+impl VerificationReport {
+	/// Create an empty report.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Get the outcomes in the order the verifiers were tried.
+	pub fn outcomes(&self) -> &[VerifierOutcome] {
+		&self.outcomes
+	}
+
+	/// Add the outcome of a verifier to the report.
+	pub fn push(&mut self, verifier: &'static str, key: Option<&str>, result: Result<()>) {
+		self.outcomes.push(VerifierOutcome{verifier, key: key.map(String::from), result});
+	}
+}
+
 /// Verifier that accepts messages if they are accepted by one of the wrapped verifiers.
 impl<Left, Right> OrVerifier<Left, Right> {
 	pub fn new(left: Left, right: Right) -> Self {
@@ -33,6 +102,77 @@ impl<Left, Right> OrVerifier<Left, Right> {
 	}
 }
 
+impl<V: Verifier> ThresholdVerifier<V> {
+	/// Create a threshold verifier that requires `threshold` of the `verifiers` to match a signature.
+	///
+	/// Returns an error if the threshold is zero or larger than the number of verifiers,
+	/// or if two verifiers have the same label.
+	pub fn new(threshold: usize, verifiers: Vec<V>) -> Result<Self> {
+		if threshold == 0 {
+			return Err(Error::other("threshold must be at least one"));
+		}
+		if threshold > verifiers.len() {
+			return Err(Error::other(format!("threshold of {} is larger than the number of verifiers ({})", threshold, verifiers.len())));
+		}
+		for (i, verifier) in verifiers.iter().enumerate() {
+			if let Some(label) = verifier.label() {
+				if verifiers[..i].iter().any(|other| other.label() == Some(label)) {
+					return Err(Error::invalid_key(format!("duplicate key in threshold verifier: {}", label)));
+				}
+			}
+		}
+		Ok(Self{threshold, verifiers})
+	}
+}
+
+impl<V> ThresholdVerifier<V> {
+	pub fn into_inner(self) -> Vec<V> {
+		self.verifiers
+	}
+
+	pub fn verifiers(&self) -> &[V] {
+		&self.verifiers
+	}
+
+	/// Get the number of verifiers that must match a signature.
+	pub fn threshold(&self) -> usize {
+		self.threshold
+	}
+
+	/// Get the error for a message that was accepted by too few verifiers.
+	///
+	/// If no verifier accepted the message, the most relevant error of the verifiers is returned.
+	pub(crate) fn threshold_error(&self, accepted: usize, error: Option<Error>) -> Error {
+		match error {
+			Some(error) if accepted == 0 => error,
+			_ => Error::invalid_signature(format!("only {} of the required {} keys matched", accepted, self.threshold())).with_part(MessagePart::Signature),
+		}
+	}
+}
+
+impl<Left, Right> AndSigner<Left, Right> {
+	pub fn new(left: Left, right: Right) -> Self {
+		Self{left, right}
+	}
+
+	pub fn into_inner(self) -> (Left, Right) {
+		(self.left, self.right)
+	}
+
+	pub fn left(&self) -> &Left {
+		&self.left
+	}
+
+	pub fn right(&self) -> &Right {
+		&self.right
+	}
+
+	/// Create a new signer that also adds the signatures of the other signer.
+	pub fn and<Other: MultiSigner>(self, other: Other) -> AndSigner<Self, Other> {
+		AndSigner::new(self, other)
+	}
+}
+
 /// Verifier that accepts messages if they are accepted by both of the wrapped verifiers.
 impl<Left, Right> AndVerifier<Left, Right> {
 	pub fn new(left: Left, right: Right) -> Self {
@@ -54,6 +194,8 @@ impl<Left, Right> AndVerifier<Left, Right> {
 
 impl<Left: Verifier, Right: Verifier> Verifier for OrVerifier<Left, Right> {
 	fn verify(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
+		#[cfg(feature = "tracing")]
+		let _span = tracing::trace_span!("or_verifier").entered();
 		// Try verifier Left first.
 		let error_a = match self.left.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature) {
 			Ok(()) => return Ok(()),
@@ -66,22 +208,63 @@ impl<Left: Verifier, Right: Verifier> Verifier for OrVerifier<Left, Right> {
 			Err(x) => x,
 		};
 
-		// Favor errors that aren't UnsupportedMacAlgorithm as returned error.
-		Err(match (error_a.kind(), error_b.kind()) {
-			(_, Error::UnsupportedMacAlgorithm) => error_a,
-			(Error::UnsupportedMacAlgorithm, _) => error_b,
-			(_, _)                              => error_a
-		})
+		Err(select_error(error_a, error_b))
+	}
+
+	fn verify_report(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8], report: &mut VerificationReport) -> Result<()> {
+		#[cfg(feature = "tracing")]
+		let _span = tracing::trace_span!("or_verifier").entered();
+		// Same as verify(), but let the wrapped verifiers add their outcomes to the report.
+		let error_a = match self.left.verify_report(protected_header, unprotected_header, encoded_header, encoded_payload, signature, report) {
+			Ok(()) => return Ok(()),
+			Err(x) => x,
+		};
+
+		let error_b = match self.right.verify_report(protected_header, unprotected_header, encoded_header, encoded_payload, signature, report) {
+			Ok(()) => return Ok(()),
+			Err(x) => x,
+		};
+
+		Err(select_error(error_a, error_b))
 	}
 }
 
 impl<Left: Verifier, Right: Verifier> Verifier for AndVerifier<Left, Right> {
 	fn verify(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
+		#[cfg(feature = "tracing")]
+		let _span = tracing::trace_span!("and_verifier").entered();
 		// Try verifier Left and Right in order, pass all errors up.
 		self.left.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature)?;
 		self.right.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature)?;
 		Ok(())
 	}
+
+	fn verify_report(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8], report: &mut VerificationReport) -> Result<()> {
+		#[cfg(feature = "tracing")]
+		let _span = tracing::trace_span!("and_verifier").entered();
+		self.left.verify_report(protected_header, unprotected_header, encoded_header, encoded_payload, signature, report)?;
+		self.right.verify_report(protected_header, unprotected_header, encoded_header, encoded_payload, signature, report)?;
+		Ok(())
+	}
+}
+
+impl<Left: MultiSigner, Right: MultiSigner> MultiSigner for AndSigner<Left, Right> {
+	fn signers(&self) -> Vec<&dyn Signer> {
+		let mut signers = self.left.signers();
+		signers.extend(self.right.signers());
+		signers
+	}
+}
+
+/// Select the error to return when both verifiers of an [`OrVerifier`] rejected a message.
+///
+/// Favor errors that aren't UnsupportedMacAlgorithm as returned error.
+pub(crate) fn select_error(error_a: Error, error_b: Error) -> Error {
+	match (error_a.kind(), error_b.kind()) {
+		(_, Error::UnsupportedMacAlgorithm) => error_a,
+		(Error::UnsupportedMacAlgorithm, _) => error_b,
+		(_, _)                              => error_a
+	}
 }
 
 #[cfg(test)]
@@ -95,10 +278,10 @@ mod test {
 	#[test]
 	fn test_encode_sign_hmac_sha2() {
 		let header = json_object!{"typ": "JWT"};
-		let signed = compact::encode_sign(header.clone(), b"foo", &Hs256Signer::new(b"secretkey")).expect("sign HS256 failed");
+		let signed = compact::encode_sign(header.clone(), b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).expect("sign HS256 failed");
 
-		let verifier_wrong = HmacVerifier::new(b"wrong-key");
-		let verifier_right = HmacVerifier::new(b"secretkey");
+		let verifier_wrong = HmacVerifier::new(b"this is not the key, even though it is long enough for HMAC-SHA512").unwrap();
+		let verifier_right = HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap();
 
 		let wrong_or_right  = verifier_wrong.clone().or(verifier_right.clone());
 		let wrong_or_wrong  = verifier_wrong.clone().or(verifier_wrong.clone());
@@ -114,4 +297,54 @@ mod test {
 		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed.as_bytes(), &wrong_or_wrong));
 		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed.as_bytes(), &wrong_and_right));
 	}
+
+	#[test]
+	fn test_verification_report() {
+		let signed = compact::encode_sign(json_object!{}, b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+
+		let wrong = HmacVerifier::new(b"this is not the key, even though it is long enough for HMAC-SHA512").unwrap();
+		let right = HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap();
+		let none  = crate::none::NoneVerifier;
+
+		// Every verifier that was tried is reported, in order.
+		let chain = wrong.clone().or(none).or(wrong.clone()).or(wrong.clone()).or(right.clone());
+		let (result, report) = compact::decode_verify_report(signed.as_bytes(), &chain);
+		assert!(let Ok(_) = result);
+		assert!(report.outcomes().len() == 5);
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = &report.outcomes()[1].result);
+		assert!(let Ok(()) = report.outcomes()[4].result);
+		assert!(report.outcomes()[4].verifier.contains("HmacVerifier"));
+		assert!(report.outcomes()[4].key == None);
+
+		// Verifiers of the same type can be told apart by their label.
+		let wrong_labelled = wrong.clone().with_label("old-key");
+		let right_labelled = right.clone().with_label("new-key");
+		let (result, report) = compact::decode_verify_report(signed.as_bytes(), &wrong_labelled.or(right_labelled));
+		assert!(let Ok(_) = result);
+		assert!(report.outcomes().len() == 2);
+		assert!(report.outcomes()[0].key.as_deref() == Some("old-key"));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = &report.outcomes()[0].result);
+		assert!(report.outcomes()[1].key.as_deref() == Some("new-key"));
+		assert!(let Ok(()) = report.outcomes()[1].result);
+
+		// Verification stops at the first verifier that accepts the message.
+		let (result, report) = compact::decode_verify_report(signed.as_bytes(), &right.clone().or(wrong.clone()));
+		assert!(let Ok(_) = result);
+		assert!(report.outcomes().len() == 1);
+
+		// The result is the same as without report.
+		let (result, report) = compact::decode_verify_report(signed.as_bytes(), &none.or(wrong.clone()));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = result);
+		assert!(report.outcomes().len() == 2);
+
+		// AndVerifier stops at the first verifier that rejects the message.
+		let (result, report) = compact::decode_verify_report(signed.as_bytes(), &wrong.clone().and(right.clone()));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = result);
+		assert!(report.outcomes().len() == 1);
+
+		// Nothing is reported if the message can not be decoded.
+		let (result, report) = compact::decode_verify_report(b"not a message", &right);
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = result);
+		assert!(report.outcomes().is_empty());
+	}
 }
@@ -7,17 +7,27 @@
 //! Most applications should use [`encode_sign`](fn.encode_sign.html) and [`decode_verify`](fn.decode_verify.html).
 //! These functions combine encoding and signing or decoding and verifying in a single step.
 
-use std::collections::BTreeMap;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 use crate::{
+	AsyncSigner,
+	AsyncVerifier,
 	Error,
 	JsonObject,
 	JsonValue,
+	MessagePart,
 	Result,
 	Signer,
+	VerificationReport,
 	Verifier,
 };
 
+#[cfg(feature = "std")]
+use crate::observer::{Observation, Operation};
+
 /// Encode a message using the JWS Compact Serialization scheme.
 ///
 /// Note that the signer should already have added it's parameters to the header.
@@ -35,7 +45,7 @@ pub fn encode(header: &JsonObject, payload: &[u8]) -> EncodedMessage {
 	let header_length = buffer.len();
 
 	buffer.push('.');
-	base64::encode_config_buf(&payload, base64::URL_SAFE_NO_PAD, &mut buffer);
+	base64::encode_config_buf(payload, base64::URL_SAFE_NO_PAD, &mut buffer);
 
 	EncodedMessage{data: buffer, header_length}
 }
@@ -46,7 +56,13 @@ pub fn encode(header: &JsonObject, payload: &[u8]) -> EncodedMessage {
 /// then encode the message and finally sign it.
 ///
 /// Using this function ensures that the header parameters are set correctly before encoding/signing.
+///
+/// With the `std` feature, the operation is reported to the global [`crate::observer::Observer`], if any.
 pub fn encode_sign(header: JsonObject, payload: &[u8], signer: &impl Signer) -> Result<EncodedSignedMessage> {
+	#[cfg(feature = "tracing")]
+	let _span = tracing::debug_span!("encode_sign").entered();
+	#[cfg(feature = "std")]
+	let observation = Observation::start(Operation::Sign);
 	let mut header = header;
 
 	// Let the signer set the headers before encoding the message.
@@ -54,17 +70,45 @@ pub fn encode_sign(header: JsonObject, payload: &[u8], signer: &impl Signer) ->
 	let encoded = encode(&header, payload);
 
 	// Sign the encoded message.
-	let signature = signer.compute_mac(encoded.header().as_bytes(), encoded.payload().as_bytes())?;
-
-	// Concat the signature to the encoded message.
-	let header_length  = encoded.header().len();
-	let payload_length = encoded.payload().len();
-	let mut data       = encoded.into_data();
-	data.reserve(base64_len(signature.len()) + 1);
-	data.push('.');
-	base64::encode_config_buf(&signature, base64::URL_SAFE_NO_PAD, &mut data);
+	let result = signer.compute_mac(encoded.header().as_bytes(), encoded.payload().as_bytes())
+		.map(|signature| encoded.with_signature(&signature));
+
+	#[cfg(feature = "tracing")]
+	crate::trace::signed(&header, result.as_ref().map(|_| ()));
+	#[cfg(feature = "std")]
+	observation.finish(Some(&header), result.as_ref().map(|_| ()));
+	result
+}
 
-	Ok(EncodedSignedMessage{data, header_length, payload_length})
+/// Encode and sign the message using an [`AsyncSigner`].
+///
+/// This is the same as [`encode_sign`], except that the signer may perform asynchronous operations,
+/// like delegating the signing to a remote key management service.
+pub async fn encode_sign_async(header: JsonObject, payload: &[u8], signer: &impl AsyncSigner) -> Result<EncodedSignedMessage> {
+	let sign = async move {
+		#[cfg(feature = "std")]
+		let observation = Observation::start(Operation::Sign);
+		let mut header = header;
+
+		// Let the signer set the headers before encoding the message.
+		signer.set_header_params_async(&mut header);
+		let encoded = encode(&header, payload);
+
+		// Sign the encoded message.
+		let result = signer.compute_mac_async(encoded.header().as_bytes(), encoded.payload().as_bytes()).await
+			.map(|signature| encoded.with_signature(&signature));
+
+		#[cfg(feature = "tracing")]
+		crate::trace::signed(&header, result.as_ref().map(|_| ()));
+		#[cfg(feature = "std")]
+		observation.finish(Some(&header), result.as_ref().map(|_| ()));
+		result
+	};
+
+	// Instrument the future instead of entering the span, so the span is not held across await points.
+	#[cfg(feature = "tracing")]
+	let sign = tracing::Instrument::instrument(sign, tracing::debug_span!("encode_sign_async"));
+	sign.await
 }
 
 /// Decode a JWS Compact Serialization message with signature from a byte slice.
@@ -93,11 +137,115 @@ pub fn decode_unverified(data: &[u8]) -> Result<(DecodedMessage, Vec<u8>)> {
 ///
 /// Note that if verification fails, you will not have access to the decoded message.
 /// If that is required, you may use [`split_encoded_parts`] and decode/verify the message manually.
+///
+/// With the `std` feature, the operation is reported to the global [`crate::observer::Observer`], if any.
 pub fn decode_verify(data: &[u8], verifier: &impl Verifier) -> Result<DecodedMessage> {
-	let parts = split_encoded_parts(data)?;
-	let (message, signature) = parts.decode()?;
-	verifier.verify(Some(&message.header), None, parts.header, parts.payload, &signature)?;
-	Ok(message)
+	#[cfg(feature = "tracing")]
+	let _span = tracing::debug_span!("decode_verify").entered();
+	#[cfg(feature = "std")]
+	let observation = Observation::start(Operation::Verify);
+
+	let (parts, (message, signature)) = match split_encoded_parts(data).and_then(|parts| Ok((parts, parts.decode()?))) {
+		Ok(decoded) => decoded,
+		Err(e) => {
+			#[cfg(feature = "tracing")]
+			crate::trace::verified(None, Err(&e));
+			#[cfg(feature = "std")]
+			observation.finish(None, Err(&e));
+			return Err(e);
+		},
+	};
+
+	let result = verifier.verify(Some(&message.header), None, parts.header, parts.payload, &signature);
+
+	#[cfg(feature = "tracing")]
+	crate::trace::verified(Some(&message.header), result.as_ref().map(|_| ()));
+	#[cfg(feature = "std")]
+	observation.finish(Some(&message.header), result.as_ref().map(|_| ()));
+	result.map(|()| message)
+}
+
+/// Decode and verify a JWS Compact Serialization message, and report the outcome of every verifier that was tried.
+///
+/// This is a diagnostic variant of [`decode_verify`] that returns the same result, together with a [`VerificationReport`].
+/// The report is empty if the message could not be decoded.
+/// It contains the error of every rejecting verifier, so it should be logged rather than returned to untrusted callers.
+///
+/// With the `std` feature, the operation is reported to the global [`crate::observer::Observer`], if any.
+pub fn decode_verify_report(data: &[u8], verifier: &impl Verifier) -> (Result<DecodedMessage>, VerificationReport) {
+	#[cfg(feature = "std")]
+	let observation = Observation::start(Operation::Verify);
+	let mut report = VerificationReport::new();
+
+	let (parts, (message, signature)) = match split_encoded_parts(data).and_then(|parts| Ok((parts, parts.decode()?))) {
+		Ok(decoded) => decoded,
+		Err(e) => {
+			#[cfg(feature = "std")]
+			observation.finish(None, Err(&e));
+			return (Err(e), report);
+		},
+	};
+
+	let result = verifier.verify_report(Some(&message.header), None, parts.header, parts.payload, &signature, &mut report);
+
+	#[cfg(feature = "std")]
+	observation.finish(Some(&message.header), result.as_ref().map(|_| ()));
+	(result.map(|()| message), report)
+}
+
+/// Decode and verify many JWS Compact Serialization messages with the same verifier.
+///
+/// Each message is decoded and verified independently, so an invalid message does not affect the others.
+/// The results are returned in the same order as the messages.
+///
+/// Verifiers that prepare their keys once, like [`crate::hmac::HmacVerifier`], do so only once for the whole batch.
+/// With the `rayon` feature, [`decode_verify_batch_parallel`] spreads the work over multiple threads.
+pub fn decode_verify_batch<Data: AsRef<[u8]>>(messages: &[Data], verifier: &impl Verifier) -> Vec<Result<DecodedMessage>> {
+	messages.iter().map(|data| decode_verify(data.as_ref(), verifier)).collect()
+}
+
+/// Decode and verify many JWS Compact Serialization messages in parallel with the same verifier.
+///
+/// This is the same as [`decode_verify_batch`], except that the messages are verified on the global [rayon](https://docs.rs/rayon) thread pool.
+/// This function is only available with the `rayon` feature.
+#[cfg(feature = "rayon")]
+pub fn decode_verify_batch_parallel<Data: AsRef<[u8]> + Sync>(messages: &[Data], verifier: &(impl Verifier + Sync)) -> Vec<Result<DecodedMessage>> {
+	use rayon::prelude::*;
+	messages.par_iter().map(|data| decode_verify(data.as_ref(), verifier)).collect()
+}
+
+/// Decode and verify a JWS Compact Serialization message using an [`AsyncVerifier`].
+///
+/// This is the same as [`decode_verify`], except that the verifier may perform asynchronous operations,
+/// like fetching keys from a remote source.
+pub async fn decode_verify_async(data: &[u8], verifier: &impl AsyncVerifier) -> Result<DecodedMessage> {
+	let verify = async move {
+		#[cfg(feature = "std")]
+		let observation = Observation::start(Operation::Verify);
+
+		let (parts, (message, signature)) = match split_encoded_parts(data).and_then(|parts| Ok((parts, parts.decode()?))) {
+			Ok(decoded) => decoded,
+			Err(e) => {
+				#[cfg(feature = "tracing")]
+				crate::trace::verified(None, Err(&e));
+				#[cfg(feature = "std")]
+				observation.finish(None, Err(&e));
+				return Err(e);
+			},
+		};
+
+		let result = verifier.verify_async(Some(&message.header), None, parts.header, parts.payload, &signature).await;
+
+		#[cfg(feature = "tracing")]
+		crate::trace::verified(Some(&message.header), result.as_ref().map(|_| ()));
+		#[cfg(feature = "std")]
+		observation.finish(Some(&message.header), result.as_ref().map(|_| ()));
+		result.map(|()| message)
+	};
+
+	#[cfg(feature = "tracing")]
+	let verify = tracing::Instrument::instrument(verify, tracing::debug_span!("decode_verify_async"));
+	verify.await
 }
 
 /// A compact JWS message with header and payload, but without signature.
@@ -137,11 +285,11 @@ impl DecodedMessage {
 	/// Create a new DecodedMessage by decoding the header and payload of a JWS Compact Serialization message.
 	pub fn from_encoded_parts(header: &[u8], payload: &[u8]) -> Result<Self> {
 		// Undo base64 encoding of parts.
-		let header  = decode_base64_url(header,  "header")?;
-		let payload = decode_base64_url(payload, "payload")?;
+		let header  = decode_base64_url(header,  MessagePart::Header)?;
+		let payload = decode_base64_url(payload, MessagePart::Payload)?;
 
 		// Decode the header as JSON.
-		let header: BTreeMap<String, JsonValue> = decode_json(&header,  "header")?;
+		let header: BTreeMap<String, JsonValue> = decode_json(&header,  MessagePart::Header)?;
 
 		// Put the decoded parts back together.
 		Ok(Self{header, payload})
@@ -149,22 +297,23 @@ impl DecodedMessage {
 
 	/// Parse the payload as JSON using serde.
 	///
-	/// The type must implement the [`serde::Deserialize`] trait
-	pub fn parse_json<'de, T: serde::de::Deserialize<'de> + 'de>(&'de self) -> std::result::Result<T, serde_json::Error> {
-		serde_json::from_slice(&self.payload)
+	/// The type must implement the [`serde::Deserialize`] trait.
+	/// If the payload can not be parsed, an [`Error::InvalidMessage`] error is returned.
+	pub fn parse_json<'de, T: serde::de::Deserialize<'de> + 'de>(&'de self) -> Result<T> {
+		decode_json(&self.payload, MessagePart::Payload)
 	}
 
 	/// Parse the payload as a [`JsonValue`].
 	///
 	/// This method avoids the need for type annotations.
-	pub fn parse_json_value(&self) -> std::result::Result<JsonValue, serde_json::Error> {
+	pub fn parse_json_value(&self) -> Result<JsonValue> {
 		self.parse_json()
 	}
 
 	/// Parse the payload as a [`JsonObject`].
 	///
 	/// This method avoids the need for type annotations.
-	pub fn parse_json_object(&self) -> std::result::Result<JsonObject, serde_json::Error> {
+	pub fn parse_json_object(&self) -> Result<JsonObject> {
 		self.parse_json()
 	}
 }
@@ -194,6 +343,18 @@ impl EncodedMessage {
 	pub fn payload(&self) -> &str {
 		&self.data[self.header_length + 1..]
 	}
+
+	/// Concat a signature to the encoded message.
+	fn with_signature(self, signature: &[u8]) -> EncodedSignedMessage {
+		let header_length  = self.header().len();
+		let payload_length = self.payload().len();
+		let mut data       = self.into_data();
+		data.reserve(base64_len(signature.len()) + 1);
+		data.push('.');
+		base64::encode_config_buf(signature, base64::URL_SAFE_NO_PAD, &mut data);
+
+		EncodedSignedMessage{data, header_length, payload_length}
+	}
 }
 
 impl EncodedSignedMessage {
@@ -228,7 +389,7 @@ impl EncodedSignedMessage {
 	}
 
 	/// Get the parts of the message as a [`CompactSerializedParts`] struct.
-	pub fn parts(&self) -> CompactSerializedParts {
+	pub fn parts(&self) -> CompactSerializedParts<'_> {
 		CompactSerializedParts {
 			header:    self.header().as_bytes(),
 			payload:   self.payload().as_bytes(),
@@ -261,7 +422,7 @@ impl<'a> CompactSerializedParts<'a> {
 	/// Decode the already-split parts of a JWS Compact Serialization message.
 	pub fn decode(&self) -> Result<(DecodedMessage, Vec<u8>)> {
 		let message   = DecodedMessage::from_encoded_parts(self.header, self.payload)?;
-		let signature = decode_base64_url(self.signature, "signature")?;
+		let signature = decode_base64_url(self.signature, MessagePart::Signature)?;
 		Ok((message, signature))
 	}
 }
@@ -274,7 +435,7 @@ impl<'a> CompactSerializedParts<'a> {
 ///   - signature
 ///
 /// This function splits a byte slice into these three parts.
-pub fn split_encoded_parts(data: &[u8]) -> Result<CompactSerializedParts> {
+pub fn split_encoded_parts(data: &[u8]) -> Result<CompactSerializedParts<'_>> {
 	// Split data into parts.
 	let mut parts = data.splitn(4, |&c| c == b'.');
 
@@ -291,24 +452,24 @@ pub fn split_encoded_parts(data: &[u8]) -> Result<CompactSerializedParts> {
 }
 
 /// Compute the length of a base64 encoded string without padding, given the input length.
-fn base64_len(input_len: usize) -> usize {
+pub(crate) fn base64_len(input_len: usize) -> usize {
 	// Multiply by 4, divide by 3 rounding up.
-	(input_len * 4 + 2) / 3
+	(input_len * 4).div_ceil(3)
 }
 
 /// Decode a base64-url encoded string.
-fn decode_base64_url(value: &[u8], field_name: &str) -> Result<Vec<u8>> {
+pub(crate) fn decode_base64_url(value: &[u8], part: MessagePart) -> Result<Vec<u8>> {
 	match base64::decode_config(value, base64::URL_SAFE_NO_PAD) {
 		Ok(x)  => Ok(x),
-		Err(_) => Err(Error::invalid_message(format!("invalid base64 in {}", field_name)))
+		Err(e) => Err(Error::invalid_message(format!("invalid base64 in {}", part)).with_part(part).maybe_with_source(e))
 	}
 }
 
 /// Decode a JSON string.
-fn decode_json<'a, T: serde::Deserialize<'a>>(value: &'a [u8], field_name: &str) -> Result<T> {
+pub(crate) fn decode_json<'a, T: serde::Deserialize<'a>>(value: &'a [u8], part: MessagePart) -> Result<T> {
 	match serde_json::from_slice(value) {
 		Ok(x)  => Ok(x),
-		Err(_) => Err(Error::invalid_message(format!("invalid JSON in {}", field_name)))
+		Err(e) => Err(Error::invalid_message(format!("invalid JSON in {}", part)).with_part(part).maybe_with_source(e))
 	}
 }
 
@@ -316,6 +477,8 @@ fn decode_json<'a, T: serde::Deserialize<'a>>(value: &'a [u8], field_name: &str)
 mod test {
 	use super::*;
 	use crate::json_object;
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+	use crate::Blocking;
 
 	use assert2::assert;
 
@@ -402,6 +565,117 @@ mod test {
 		assert!(&signature[..] == RFC7515_A1_SIGNATURE);
 	}
 
+	#[test]
+	fn test_decode_error_details() {
+		let error = split_encoded_parts(b"eyJhbGciOiJIUzI1NiJ9.not*base64.").unwrap().decode().unwrap_err();
+		assert!(error.kind() == Error::InvalidMessage);
+		assert!(error.part() == Some(MessagePart::Payload));
+		#[cfg(feature = "std")]
+		assert!(let Some(_) = std::error::Error::source(&error).and_then(|source| source.downcast_ref::<base64::DecodeError>()));
+
+		let error = split_encoded_parts(b"bm90IGpzb24.Zm9v.").unwrap().decode().unwrap_err();
+		assert!(error.part() == Some(MessagePart::Header));
+		#[cfg(feature = "std")]
+		assert!(let Some(_) = std::error::Error::source(&error).and_then(|source| source.downcast_ref::<serde_json::Error>()));
+
+		let error = crate::parse_required_header_param::<&str>(Some(&json_object!{"alg": 1}), None, "alg").unwrap_err();
+		assert!(error.kind() == Error::InvalidHeaderParam);
+		assert!(error.part() == Some(MessagePart::Header));
+		assert!(error.header_param() == Some("alg"));
+
+		let error = crate::get_required_header_param(Some(&json_object!{}), None, "kid").unwrap_err();
+		assert!(error.kind() == Error::MissingHeaderParam);
+		assert!(error.header_param() == Some("kid"));
+	}
+
+	/// Verifier that looks up HMAC keys by `kid` from an in-process key source.
+	///
+	/// The lookup yields to the executor once to behave like a remote key source.
+	struct MockKeySource {
+		keys: BTreeMap<String, Vec<u8>>,
+	}
+
+	impl AsyncVerifier for MockKeySource {
+		async fn verify_async(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
+			let kid : &str = crate::parse_required_header_param(protected_header, unprotected_header, "kid")?;
+
+			let mut yielded = false;
+			futures::future::poll_fn(|context| {
+				if std::mem::replace(&mut yielded, true) {
+					std::task::Poll::Ready(())
+				} else {
+					context.waker().wake_by_ref();
+					std::task::Poll::Pending
+				}
+			}).await;
+
+			let key = self.keys.get(kid).ok_or_else(|| Error::unsupported_mac_algorithm(format!("unknown key: {}", kid)))?;
+			HmacVerifier::new(key)?.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature)
+		}
+	}
+
+	#[test]
+	fn test_decode_verify_batch() {
+		let signer   = Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap();
+		let verifier = HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap();
+		let wrong    = Hs256Signer::new(b"this is not the key, even though it is long enough for HMAC-SHA512").unwrap();
+
+		let messages = vec![
+			encode_sign(json_object!{}, b"foo", &signer).unwrap().into_data(),
+			encode_sign(json_object!{}, b"bar", &wrong).unwrap().into_data(),
+			String::from("not-a-message"),
+			encode_sign(json_object!{}, b"baz", &signer).unwrap().into_data(),
+		];
+
+		let results = decode_verify_batch(&messages, &verifier);
+		assert!(results.len() == 4);
+		assert!(results[0].as_ref().unwrap().payload == b"foo");
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = &results[1]);
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = &results[2]);
+		assert!(results[3].as_ref().unwrap().payload == b"baz");
+
+		#[cfg(feature = "rayon")]
+		{
+			let summary = |results: &[Result<DecodedMessage>]| results.iter()
+				.map(|x| x.as_ref().map(|x| x.payload.clone()).map_err(|e| e.kind()))
+				.collect::<Vec<_>>();
+			assert!(summary(&decode_verify_batch_parallel(&messages, &verifier)) == summary(&results));
+		}
+	}
+
+	#[test]
+	fn test_decode_verify_async() {
+		let mut keys = BTreeMap::new();
+		keys.insert(String::from("key-1"), b"this is a secret key that is long enough for all HMAC algorithms".to_vec());
+		let key_source = MockKeySource{keys};
+
+		let signed  = encode_sign(json_object!{"kid": "key-1"}, b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		let decoded = futures::executor::block_on(decode_verify_async(signed.as_bytes(), &key_source)).unwrap();
+		assert!(decoded.payload == b"foo");
+
+		let signed = encode_sign(json_object!{"kid": "key-1"}, b"foo", &Hs256Signer::new(b"this is not the key, even though it is long enough for HMAC-SHA512").unwrap()).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = futures::executor::block_on(decode_verify_async(signed.as_bytes(), &key_source)));
+
+		let signed = encode_sign(json_object!{"kid": "key-2"}, b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = futures::executor::block_on(decode_verify_async(signed.as_bytes(), &key_source)));
+
+		// Synchronous verifiers can be used as asynchronous verifiers through the blocking adapter.
+		let signed = encode_sign(json_object!{}, b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		assert!(let Ok(_) = futures::executor::block_on(decode_verify_async(signed.as_bytes(), &Blocking(HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()))));
+	}
+
+	#[test]
+	fn test_encode_sign_async() {
+		// Synchronous signers can be used as asynchronous signers through the blocking adapter.
+		let signer = Blocking(Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap());
+		let mut header = JsonObject::new();
+		signer.set_header_params_async(&mut header);
+		assert!(header.get("alg") == Some(&JsonValue::from("HS256")));
+
+		let signed = futures::executor::block_on(encode_sign_async(json_object!{}, b"foo", &signer)).unwrap();
+		assert!(signed.data() == encode_sign(json_object!{}, b"foo", &signer.0).unwrap().data());
+	}
+
 	#[test]
 	fn test_encode() {
 		let header  = json_object!{"typ": "JWT", "alg": "HS256"};
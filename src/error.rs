@@ -1,12 +1,20 @@
 //! Error types for this crate.
 
-use std::fmt;
+use core::fmt;
 
-/// [`std::result::Result`] with the error type filled in.
-pub type Result<T> = std::result::Result<T, Error>;
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+
+/// [`core::result::Result`] with the error type filled in.
+pub type Result<T> = core::result::Result<T, Error>;
 
 /// Indicates the type of an error that can occur during JWS processing.
+///
+/// New kinds of errors may be added in minor releases.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum ErrorKind {
 	/// A required header parameter is missing.
 	MissingHeaderParam,
@@ -23,10 +31,47 @@ pub enum ErrorKind {
 	/// The signature of a message being verified is invalid.
 	InvalidSignature,
 
+	/// The key management or content encryption algorithm indicated by the JWE header is not supported by the used [`crate::jwe::Decrypter`].
+	UnsupportedEncryptionAlgorithm,
+
+	/// The content of an encrypted message could not be decrypted or its authentication tag is invalid.
+	DecryptionFailed,
+
+	/// A key is not suitable for the algorithm, for example because it is too short.
+	InvalidKey,
+
 	/// An error that doesn't match any of the other types.
 	Other,
 }
 
+/// The part of a message that an error relates to.
+///
+/// New parts may be added in minor releases.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum MessagePart {
+	/// The (protected) header.
+	Header,
+
+	/// The payload.
+	Payload,
+
+	/// The signature.
+	Signature,
+
+	/// The encrypted key of an encrypted message.
+	EncryptedKey,
+
+	/// The initialization vector of an encrypted message.
+	InitializationVector,
+
+	/// The ciphertext of an encrypted message.
+	Ciphertext,
+
+	/// The authentication tag of an encrypted message.
+	AuthenticationTag,
+}
+
 /// An error that can occur during JWS processing.
 ///
 /// An error consists of an [`ErrorKind`] indicating the type of error,
@@ -34,27 +79,47 @@ pub enum ErrorKind {
 ///
 /// The message is purely for human consumption.
 /// It should not be used by error handling code to change handling logic.
+/// Instead, errors may carry structured details:
+/// the [`MessagePart`] that was rejected, the name of the offending header parameter and the algorithm involved,
+/// available through [`Error::part`], [`Error::header_param`] and [`Error::algorithm`].
+/// With the `std` feature, the underlying error is available through [`std::error::Error::source`].
 #[derive(Clone, Debug)]
 pub struct Error{
-	pub kind:    ErrorKind,
-	pub message: String,
+	pub kind:         ErrorKind,
+	pub message:      String,
+	part:             Option<MessagePart>,
+	header_param:     Option<String>,
+	algorithm:        Option<String>,
+	#[cfg(feature = "std")]
+	source:           Option<Arc<dyn std::error::Error + Send + Sync>>,
 }
 
 impl ErrorKind {
 	fn with_message(self, message: impl Into<String>) -> Error {
-		Error{kind: self, message: message.into()}
+		Error{
+			kind:         self,
+			message:      message.into(),
+			part:         None,
+			header_param: None,
+			algorithm:    None,
+			#[cfg(feature = "std")]
+			source:       None,
+		}
 	}
 }
 
 impl Error {
 	#![allow(non_upper_case_globals)]
 
-	pub const Other                   : ErrorKind = ErrorKind::Other;
-	pub const MissingHeaderParam      : ErrorKind = ErrorKind::MissingHeaderParam;
-	pub const InvalidHeaderParam      : ErrorKind = ErrorKind::InvalidHeaderParam;
-	pub const UnsupportedMacAlgorithm : ErrorKind = ErrorKind::UnsupportedMacAlgorithm;
-	pub const InvalidMessage          : ErrorKind = ErrorKind::InvalidMessage;
-	pub const InvalidSignature        : ErrorKind = ErrorKind::InvalidSignature;
+	pub const Other                          : ErrorKind = ErrorKind::Other;
+	pub const MissingHeaderParam             : ErrorKind = ErrorKind::MissingHeaderParam;
+	pub const InvalidHeaderParam             : ErrorKind = ErrorKind::InvalidHeaderParam;
+	pub const UnsupportedMacAlgorithm        : ErrorKind = ErrorKind::UnsupportedMacAlgorithm;
+	pub const InvalidMessage                 : ErrorKind = ErrorKind::InvalidMessage;
+	pub const InvalidSignature               : ErrorKind = ErrorKind::InvalidSignature;
+	pub const UnsupportedEncryptionAlgorithm : ErrorKind = ErrorKind::UnsupportedEncryptionAlgorithm;
+	pub const DecryptionFailed               : ErrorKind = ErrorKind::DecryptionFailed;
+	pub const InvalidKey                     : ErrorKind = ErrorKind::InvalidKey;
 
 	/// Get the kind of error.
 	pub fn kind(&self) -> ErrorKind {
@@ -66,6 +131,61 @@ impl Error {
 		&self.message
 	}
 
+	/// Get the part of the message that the error relates to, if known.
+	pub fn part(&self) -> Option<MessagePart> {
+		self.part
+	}
+
+	/// Get the name of the header parameter that caused the error, if any.
+	pub fn header_param(&self) -> Option<&str> {
+		self.header_param.as_deref()
+	}
+
+	/// Get the algorithm involved in the error, if any.
+	pub fn algorithm(&self) -> Option<&str> {
+		self.algorithm.as_deref()
+	}
+
+	/// Set the part of the message that the error relates to.
+	pub fn with_part(mut self, part: MessagePart) -> Self {
+		self.part = Some(part);
+		self
+	}
+
+	/// Set the name of the header parameter that caused the error.
+	///
+	/// This also sets the message part to [`MessagePart::Header`].
+	pub fn with_header_param(mut self, name: impl Into<String>) -> Self {
+		self.part = Some(MessagePart::Header);
+		self.header_param = Some(name.into());
+		self
+	}
+
+	/// Set the algorithm involved in the error.
+	pub fn with_algorithm(mut self, algorithm: impl Into<String>) -> Self {
+		self.algorithm = Some(algorithm.into());
+		self
+	}
+
+	/// Set the underlying error that caused this error.
+	#[cfg(feature = "std")]
+	pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+		self.source = Some(Arc::new(source));
+		self
+	}
+
+	/// Set the underlying error if the `std` feature is enabled, or discard it otherwise.
+	#[cfg(feature = "std")]
+	pub(crate) fn maybe_with_source(self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+		self.with_source(source)
+	}
+
+	/// Set the underlying error if the `std` feature is enabled, or discard it otherwise.
+	#[cfg(not(feature = "std"))]
+	pub(crate) fn maybe_with_source<E>(self, _source: E) -> Self {
+		self
+	}
+
 	/// Create a new error of type [`ErrorKind::Other`] with a given message.
 	pub fn other(message: impl Into<String>) -> Self {
 		ErrorKind::Other.with_message(message)
@@ -95,18 +215,50 @@ impl Error {
 	pub fn invalid_signature(message: impl Into<String>) -> Self {
 		ErrorKind::InvalidSignature.with_message(message)
 	}
+
+	/// Create a new error of type [`ErrorKind::UnsupportedEncryptionAlgorithm`] with a given message.
+	pub fn unsupported_encryption_algorithm(message: impl Into<String>) -> Self {
+		ErrorKind::UnsupportedEncryptionAlgorithm.with_message(message)
+	}
+
+	/// Create a new error of type [`ErrorKind::DecryptionFailed`] with a given message.
+	pub fn decryption_failed(message: impl Into<String>) -> Self {
+		ErrorKind::DecryptionFailed.with_message(message)
+	}
+
+	/// Create a new error of type [`ErrorKind::InvalidKey`] with a given message.
+	pub fn invalid_key(message: impl Into<String>) -> Self {
+		ErrorKind::InvalidKey.with_message(message)
+	}
 }
 
 
+impl fmt::Display for MessagePart {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			MessagePart::Header               => write!(formatter, "header"),
+			MessagePart::Payload              => write!(formatter, "payload"),
+			MessagePart::Signature            => write!(formatter, "signature"),
+			MessagePart::EncryptedKey         => write!(formatter, "encrypted key"),
+			MessagePart::InitializationVector => write!(formatter, "initialization vector"),
+			MessagePart::Ciphertext           => write!(formatter, "ciphertext"),
+			MessagePart::AuthenticationTag    => write!(formatter, "authentication tag"),
+		}
+	}
+}
+
 impl fmt::Display for ErrorKind {
 	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
 		match self {
-			ErrorKind::Other                   => write!(formatter, "unspecified error"),
-			ErrorKind::MissingHeaderParam      => write!(formatter, "missing header parameter"),
-			ErrorKind::InvalidHeaderParam      => write!(formatter, "invalid header parameter"),
-			ErrorKind::UnsupportedMacAlgorithm => write!(formatter, "unsupported MAC algorithm"),
-			ErrorKind::InvalidMessage          => write!(formatter, "invalid message"),
-			ErrorKind::InvalidSignature        => write!(formatter, "invalid signature"),
+			ErrorKind::Other                          => write!(formatter, "unspecified error"),
+			ErrorKind::MissingHeaderParam             => write!(formatter, "missing header parameter"),
+			ErrorKind::InvalidHeaderParam             => write!(formatter, "invalid header parameter"),
+			ErrorKind::UnsupportedMacAlgorithm        => write!(formatter, "unsupported MAC algorithm"),
+			ErrorKind::InvalidMessage                 => write!(formatter, "invalid message"),
+			ErrorKind::InvalidSignature               => write!(formatter, "invalid signature"),
+			ErrorKind::UnsupportedEncryptionAlgorithm => write!(formatter, "unsupported encryption algorithm"),
+			ErrorKind::DecryptionFailed               => write!(formatter, "decryption failed"),
+			ErrorKind::InvalidKey                     => write!(formatter, "invalid key"),
 		}
 	}
 }
@@ -120,3 +272,62 @@ impl fmt::Display for Error {
 		}
 	}
 }
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Self {
+		Error::invalid_message(format!("invalid JSON: {}", error)).maybe_with_source(error)
+	}
+}
+
+impl From<base64::DecodeError> for Error {
+	fn from(error: base64::DecodeError) -> Self {
+		Error::invalid_message(format!("invalid base64: {}", error)).maybe_with_source(error)
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.source.as_deref().map(|source| source as &(dyn std::error::Error + 'static))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	#[cfg(feature = "std")]
+	use crate::{compact::DecodedMessage, json_object};
+
+	use assert2::assert;
+
+	#[test]
+	fn test_from_conversions() {
+		let error = Error::from(serde_json::from_slice::<crate::JsonValue>(b"{").unwrap_err());
+		assert!(error.kind() == Error::InvalidMessage);
+		#[cfg(feature = "std")]
+		assert!(let Some(_) = std::error::Error::source(&error).and_then(|source| source.downcast_ref::<serde_json::Error>()));
+
+		let error = Error::from(base64::decode("!").unwrap_err());
+		assert!(error.kind() == Error::InvalidMessage);
+		#[cfg(feature = "std")]
+		assert!(let Some(_) = std::error::Error::source(&error).and_then(|source| source.downcast_ref::<base64::DecodeError>()));
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_std_error() {
+		// The error converts to boxed errors, like those used by `anyhow`.
+		fn parse_payload(payload: &[u8]) -> core::result::Result<crate::JsonValue, Box<dyn std::error::Error + Send + Sync>> {
+			let message = DecodedMessage{header: json_object!{}, payload: payload.to_vec()};
+			Ok(message.parse_json_value()?)
+		}
+
+		assert!(let Ok(_) = parse_payload(b"{}"));
+
+		let error = parse_payload(b"not json").unwrap_err();
+		let error = error.downcast_ref::<Error>().unwrap();
+		assert!(error.kind() == Error::InvalidMessage);
+		assert!(error.part() == Some(MessagePart::Payload));
+		assert!(let Some(_) = std::error::Error::source(error));
+	}
+}
@@ -0,0 +1,426 @@
+//! C API for signing and verifying messages in the JWS Compact Serialization format.
+//!
+//! This module is only available with the `cdylib` feature.
+//! The matching C header is `include/jws.h`, which is generated from this module with [`cbindgen`](https://github.com/mozilla/cbindgen).
+//! Build the shared library with `cargo rustc --release --lib --features cdylib --crate-type cdylib`.
+//!
+//! All functions return a [`JwsStatus`].
+//! When a function fails, [`jws_last_error_message`] returns a description of the error.
+//! Strings and byte buffers returned by the library must be released with [`jws_string_free`] and [`jws_bytes_free`].
+//!
+//! Keys are passed as opaque [`JwsKey`] handles.
+//! Currently, only HMAC keys are supported.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::compact::{self, DecodedMessage};
+use crate::hmac::{HmacVerifier, Hs256Signer, Hs384Signer, Hs512Signer};
+use crate::{Error, ErrorKind, JsonObject};
+
+/// Status code returned by all functions of the C API.
+///
+/// The error codes correspond to the variants of [`ErrorKind`].
+/// The numeric values are part of the ABI and will not change.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum JwsStatus {
+	/// The function succeeded.
+	Ok = 0,
+
+	/// A required header parameter is missing.
+	MissingHeaderParam = 1,
+
+	/// A header parameter was found but its value is invalid.
+	InvalidHeaderParam = 2,
+
+	/// The MAC algorithm indicated by the message header or requested for a key is not supported.
+	UnsupportedMacAlgorithm = 3,
+
+	/// The message being processed is not valid.
+	InvalidMessage = 4,
+
+	/// The signature of the message being verified is invalid.
+	InvalidSignature = 5,
+
+	/// The encryption algorithm is not supported.
+	UnsupportedEncryptionAlgorithm = 6,
+
+	/// The content of an encrypted message could not be decrypted.
+	DecryptionFailed = 7,
+
+	/// A key is not suitable for the algorithm, for example because it is too short.
+	InvalidKey = 8,
+
+	/// An error that doesn't match any of the other types.
+	Other = 9,
+
+	/// A required pointer argument is null or a string argument is not valid UTF-8.
+	InvalidArgument = 10,
+
+	/// The library panicked.
+	Panic = 11,
+}
+
+/// Opaque handle to a key that can be used to sign and verify messages.
+///
+/// Create a key with [`jws_key_new_hmac`] and release it with [`jws_key_free`].
+/// The key material is zeroized when the key is released.
+pub struct JwsKey {
+	signer   : HmacSigner,
+	verifier : HmacVerifier<Vec<u8>>,
+}
+
+enum HmacSigner {
+	Hs256(Hs256Signer<Vec<u8>>),
+	Hs384(Hs384Signer<Vec<u8>>),
+	Hs512(Hs512Signer<Vec<u8>>),
+}
+
+/// Error returned by the implementation of the C API functions.
+enum FfiError {
+	Jws(Error),
+
+	/// The named argument is null or not valid UTF-8.
+	InvalidArgument(&'static str),
+}
+
+impl From<Error> for FfiError {
+	fn from(error: Error) -> Self {
+		FfiError::Jws(error)
+	}
+}
+
+impl From<ErrorKind> for JwsStatus {
+	fn from(kind: ErrorKind) -> Self {
+		match kind {
+			ErrorKind::MissingHeaderParam             => JwsStatus::MissingHeaderParam,
+			ErrorKind::InvalidHeaderParam             => JwsStatus::InvalidHeaderParam,
+			ErrorKind::UnsupportedMacAlgorithm        => JwsStatus::UnsupportedMacAlgorithm,
+			ErrorKind::InvalidMessage                 => JwsStatus::InvalidMessage,
+			ErrorKind::InvalidSignature               => JwsStatus::InvalidSignature,
+			ErrorKind::UnsupportedEncryptionAlgorithm => JwsStatus::UnsupportedEncryptionAlgorithm,
+			ErrorKind::DecryptionFailed               => JwsStatus::DecryptionFailed,
+			ErrorKind::InvalidKey                     => JwsStatus::InvalidKey,
+			ErrorKind::Other                          => JwsStatus::Other,
+		}
+	}
+}
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Create a new HMAC key.
+///
+/// The algorithm must be one of `HS256`, `HS384` or `HS512`.
+/// It determines the algorithm used for signing.
+/// Verification accepts all three algorithms, as indicated by the `alg` header parameter of the message.
+///
+/// On success, the key handle is written to `out_key`.
+///
+/// # Safety
+/// `algorithm` must be a valid nul-terminated string, `key` must point to `key_len` readable bytes
+/// and `out_key` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jws_key_new_hmac(algorithm: *const c_char, key: *const u8, key_len: usize, out_key: *mut *mut JwsKey) -> JwsStatus {
+	ffi_call(|| {
+		let algorithm = c_str(algorithm, "algorithm")?;
+		let key       = bytes(key, key_len, "key")?.to_vec();
+		let out_key   = out_ptr(out_key, "out_key")?;
+
+		let signer = match algorithm {
+			"HS256" => HmacSigner::Hs256(Hs256Signer::new(key.clone())?),
+			"HS384" => HmacSigner::Hs384(Hs384Signer::new(key.clone())?),
+			"HS512" => HmacSigner::Hs512(Hs512Signer::new(key.clone())?),
+			alg     => return Err(Error::unsupported_mac_algorithm(alg.to_string()).into()),
+		};
+		let verifier = HmacVerifier::new(key)?;
+
+		*out_key = Box::into_raw(Box::new(JwsKey{signer, verifier}));
+		Ok(())
+	})
+}
+
+/// Release a key created with [`jws_key_new_hmac`].
+///
+/// Passing a null pointer is allowed and does nothing.
+///
+/// # Safety
+/// `key` must be null or a key handle that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn jws_key_free(key: *mut JwsKey) {
+	if !key.is_null() {
+		drop(Box::from_raw(key));
+	}
+}
+
+/// Sign a payload and encode it using the JWS Compact Serialization format.
+///
+/// `header_json` may be null or a JSON object with additional header parameters.
+/// On success, the encoded message is written to `out_token` and must be released with [`jws_string_free`].
+///
+/// # Safety
+/// `key` must be a valid key handle, `header_json` must be null or a valid nul-terminated string,
+/// `payload` must point to `payload_len` readable bytes and `out_token` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jws_sign_compact(
+	key         : *const JwsKey,
+	header_json : *const c_char,
+	payload     : *const u8,
+	payload_len : usize,
+	out_token   : *mut *mut c_char,
+) -> JwsStatus {
+	ffi_call(|| {
+		let key       = key.as_ref().ok_or(FfiError::InvalidArgument("key"))?;
+		let payload   = bytes(payload, payload_len, "payload")?;
+		let out_token = out_ptr(out_token, "out_token")?;
+		let header : JsonObject = if header_json.is_null() {
+			JsonObject::new()
+		} else {
+			serde_json::from_str(c_str(header_json, "header_json")?).map_err(|e| Error::invalid_header_param(format!("failed to parse header: {}", e)))?
+		};
+
+		let encoded = match &key.signer {
+			HmacSigner::Hs256(signer) => compact::encode_sign(header, payload, signer)?,
+			HmacSigner::Hs384(signer) => compact::encode_sign(header, payload, signer)?,
+			HmacSigner::Hs512(signer) => compact::encode_sign(header, payload, signer)?,
+		};
+
+		*out_token = into_c_string(encoded.into_data())?;
+		Ok(())
+	})
+}
+
+/// Decode a message in the JWS Compact Serialization format and verify the signature.
+///
+/// On success, the header is written to `out_header_json` as JSON object and the payload is written to `out_payload` and `out_payload_len`.
+/// Each output pointer may be null if the value is not needed,
+/// except that `out_payload_len` is required when `out_payload` is given.
+/// The returned header must be released with [`jws_string_free`] and the payload with [`jws_bytes_free`].
+///
+/// # Safety
+/// `key` must be a valid key handle, `token` must be a valid nul-terminated string
+/// and the output pointers must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jws_verify_compact(
+	key             : *const JwsKey,
+	token           : *const c_char,
+	out_header_json : *mut *mut c_char,
+	out_payload     : *mut *mut u8,
+	out_payload_len : *mut usize,
+) -> JwsStatus {
+	ffi_call(|| {
+		let key     = key.as_ref().ok_or(FfiError::InvalidArgument("key"))?;
+		let token   = c_str(token, "token")?;
+		let message = compact::decode_verify(token.as_bytes(), &key.verifier)?;
+		write_message(message, out_header_json, out_payload, out_payload_len)
+	})
+}
+
+/// Decode a message in the JWS Compact Serialization format without verifying the signature.
+///
+/// The outputs are the same as for [`jws_verify_compact`].
+/// Do not trust the decoded message before it has been verified.
+///
+/// # Safety
+/// `token` must be a valid nul-terminated string and the output pointers must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jws_decode_unverified(
+	token           : *const c_char,
+	out_header_json : *mut *mut c_char,
+	out_payload     : *mut *mut u8,
+	out_payload_len : *mut usize,
+) -> JwsStatus {
+	ffi_call(|| {
+		let token = c_str(token, "token")?;
+		let (message, _signature) = compact::decode_unverified(token.as_bytes())?;
+		write_message(message, out_header_json, out_payload, out_payload_len)
+	})
+}
+
+/// Get a description of the last error that occurred on the calling thread.
+///
+/// Returns null if the last function call succeeded.
+/// The returned string is owned by the library and remains valid until the next call to the library on the same thread.
+#[no_mangle]
+pub extern "C" fn jws_last_error_message() -> *const c_char {
+	LAST_ERROR.with(|last_error| {
+		last_error.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr())
+	})
+}
+
+/// Release a string returned by the library.
+///
+/// Passing a null pointer is allowed and does nothing.
+///
+/// # Safety
+/// `string` must be null or a string returned by the library that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn jws_string_free(string: *mut c_char) {
+	if !string.is_null() {
+		drop(CString::from_raw(string));
+	}
+}
+
+/// Release a byte buffer returned by the library.
+///
+/// Passing a null pointer is allowed and does nothing.
+///
+/// # Safety
+/// `data` must be null or a buffer returned by the library that has not been released yet,
+/// and `len` must be the length that was returned with it.
+#[no_mangle]
+pub unsafe extern "C" fn jws_bytes_free(data: *mut u8, len: usize) {
+	if !data.is_null() {
+		drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+	}
+}
+
+/// Run the implementation of a C API function, record the error message and convert the result to a status code.
+fn ffi_call(function: impl FnOnce() -> Result<(), FfiError>) -> JwsStatus {
+	let (status, message) = match catch_unwind(AssertUnwindSafe(function)) {
+		Ok(Ok(()))                               => (JwsStatus::Ok, None),
+		Ok(Err(FfiError::Jws(error)))            => (error.kind().into(), Some(error.to_string())),
+		Ok(Err(FfiError::InvalidArgument(name))) => (JwsStatus::InvalidArgument, Some(format!("invalid argument: {}", name))),
+		Err(_)                                   => (JwsStatus::Panic, Some("panic in jws library".to_string())),
+	};
+
+	// Error messages never contain nul bytes, but stay on the safe side.
+	let message = message.map(|message| CString::new(message.replace('\0', "")).unwrap_or_default());
+	LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+	status
+}
+
+unsafe fn c_str<'a>(string: *const c_char, name: &'static str) -> Result<&'a str, FfiError> {
+	if string.is_null() {
+		return Err(FfiError::InvalidArgument(name));
+	}
+	CStr::from_ptr(string).to_str().map_err(|_| FfiError::InvalidArgument(name))
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize, name: &'static str) -> Result<&'a [u8], FfiError> {
+	if len == 0 {
+		Ok(&[])
+	} else if data.is_null() {
+		Err(FfiError::InvalidArgument(name))
+	} else {
+		Ok(std::slice::from_raw_parts(data, len))
+	}
+}
+
+unsafe fn out_ptr<'a, T>(ptr: *mut T, name: &'static str) -> Result<&'a mut T, FfiError> {
+	ptr.as_mut().ok_or(FfiError::InvalidArgument(name))
+}
+
+fn into_c_string(string: String) -> Result<*mut c_char, FfiError> {
+	let string = CString::new(string).map_err(|_| Error::other("string contains a nul byte"))?;
+	Ok(string.into_raw())
+}
+
+unsafe fn write_message(message: DecodedMessage, out_header_json: *mut *mut c_char, out_payload: *mut *mut u8, out_payload_len: *mut usize) -> Result<(), FfiError> {
+	if !out_payload.is_null() && out_payload_len.is_null() {
+		return Err(FfiError::InvalidArgument("out_payload_len"));
+	}
+
+	if let Some(out_header_json) = out_header_json.as_mut() {
+		let header = serde_json::to_string(&message.header).map_err(|e| Error::other(format!("failed to serialize header: {}", e)))?;
+		*out_header_json = into_c_string(header)?;
+	}
+
+	if let Some(out_payload_len) = out_payload_len.as_mut() {
+		*out_payload_len = message.payload.len();
+	}
+
+	if let Some(out_payload) = out_payload.as_mut() {
+		*out_payload = Box::into_raw(message.payload.into_boxed_slice()) as *mut u8;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::assert;
+	use std::ptr::{null, null_mut};
+
+	const KEY : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+
+	fn new_key(algorithm: &str, key: &[u8]) -> (JwsStatus, *mut JwsKey) {
+		let algorithm = CString::new(algorithm).unwrap();
+		let mut handle = null_mut();
+		let status = unsafe { jws_key_new_hmac(algorithm.as_ptr(), key.as_ptr(), key.len(), &mut handle) };
+		(status, handle)
+	}
+
+	fn last_error() -> String {
+		unsafe { CStr::from_ptr(jws_last_error_message()) }.to_str().unwrap().to_string()
+	}
+
+	#[test]
+	fn test_sign_verify() {
+		let (status, key) = new_key("HS384", KEY);
+		assert!(status == JwsStatus::Ok);
+		assert!(jws_last_error_message().is_null());
+
+		let header = CString::new(r#"{"typ": "JWT"}"#).unwrap();
+		let mut token = null_mut();
+		assert!(unsafe { jws_sign_compact(key, header.as_ptr(), b"foo".as_ptr(), 3, &mut token) } == JwsStatus::Ok);
+
+		let mut header_json = null_mut();
+		let mut payload     = null_mut();
+		let mut payload_len = 0;
+		assert!(unsafe { jws_verify_compact(key, token, &mut header_json, &mut payload, &mut payload_len) } == JwsStatus::Ok);
+		assert!(unsafe { CStr::from_ptr(header_json) }.to_str() == Ok(r#"{"alg":"HS384","typ":"JWT"}"#));
+		assert!(unsafe { std::slice::from_raw_parts(payload, payload_len) } == b"foo");
+
+		// The Rust API accepts the token produced through the C API.
+		let decoded = compact::decode_verify(unsafe { CStr::from_ptr(token) }.to_bytes(), &HmacVerifier::new(KEY).unwrap());
+		assert!(let Ok(_) = decoded);
+
+		unsafe {
+			jws_string_free(header_json);
+			jws_bytes_free(payload, payload_len);
+			jws_string_free(token);
+			jws_key_free(key);
+		}
+	}
+
+	#[test]
+	fn test_verify_errors() {
+		let (_, key) = new_key("HS256", KEY);
+		let token = compact::encode_sign(JsonObject::new(), b"foo", &Hs256Signer::new(KEY).unwrap()).unwrap();
+
+		let tampered = CString::new(token.data().replace(".Zm9v.", ".YmFy.")).unwrap();
+		assert!(unsafe { jws_verify_compact(key, tampered.as_ptr(), null_mut(), null_mut(), null_mut()) } == JwsStatus::InvalidSignature);
+		assert!(last_error() == "invalid signature");
+
+		// The unverified message can still be decoded.
+		let mut payload_len = 0;
+		assert!(unsafe { jws_decode_unverified(tampered.as_ptr(), null_mut(), null_mut(), &mut payload_len) } == JwsStatus::Ok);
+		assert!(payload_len == 3);
+
+		let garbage = CString::new("not a token").unwrap();
+		assert!(unsafe { jws_verify_compact(key, garbage.as_ptr(), null_mut(), null_mut(), null_mut()) } == JwsStatus::InvalidMessage);
+
+		// The payload length is required when the payload is requested.
+		let token = CString::new(token.into_data()).unwrap();
+		let mut payload = null_mut();
+		assert!(unsafe { jws_verify_compact(key, token.as_ptr(), null_mut(), &mut payload, null_mut()) } == JwsStatus::InvalidArgument);
+		assert!(payload.is_null());
+		assert!(unsafe { jws_verify_compact(null(), tampered.as_ptr(), null_mut(), null_mut(), null_mut()) } == JwsStatus::InvalidArgument);
+		assert!(last_error() == "invalid argument: key");
+
+		unsafe { jws_key_free(key) };
+	}
+
+	#[test]
+	fn test_invalid_keys() {
+		assert!(let (JwsStatus::InvalidKey, _) = new_key("HS256", b"short"));
+		assert!(let (JwsStatus::UnsupportedMacAlgorithm, _) = new_key("RS256", KEY));
+		assert!(unsafe { jws_key_new_hmac(null(), KEY.as_ptr(), KEY.len(), &mut null_mut()) } == JwsStatus::InvalidArgument);
+	}
+}
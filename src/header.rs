@@ -29,7 +29,7 @@ pub fn get_header_param<'a>(protected: Option<&'a JsonObject>, unprotected: Opti
 ///
 /// This is almost identical to [`get`](#method.get), except that this function returns a properly formatter error instead of an empty optional.
 pub fn get_required_header_param<'a>(protected: Option<&'a JsonObject>, unprotected: Option<&'a JsonObject>, key: &str) -> Result<&'a JsonValue> {
-	Ok(get_header_param(protected, unprotected, key).ok_or_else(|| Error::missing_header_param(key))?)
+	get_header_param(protected, unprotected, key).ok_or_else(|| Error::missing_header_param(key).with_header_param(key))
 }
 
 /// Get and deserialize a required parameter from either header.
@@ -38,6 +38,6 @@ pub fn get_required_header_param<'a>(protected: Option<&'a JsonObject>, unprotec
 /// Deserialization errors are reported as [`Error::InvalidHeaderParam`].
 pub fn parse_required_header_param<'a, T: serde::Deserialize<'a> + 'a>(protected: Option<&'a JsonObject>, unprotected: Option<&'a JsonObject>, key: &str) -> Result<T> {
 	let value = get_required_header_param(protected, unprotected, key)?;
-	let value = T::deserialize(value).map_err(|_| Error::invalid_header_param(key))?;
+	let value = T::deserialize(value).map_err(|e| Error::invalid_header_param(key).with_header_param(key).maybe_with_source(e))?;
 	Ok(value)
 }
@@ -1,9 +1,16 @@
 //! HMAC [`Verifier`] and [`Signer`] implementations using [RustCrypto](https://github.com/RustCrypto).
 
 use crypto_mac::{Mac, NewMac};
+use crypto_mac::generic_array::GenericArray;
+use crypto_mac::generic_array::typenum::Unsigned;
 use hmac::Hmac;
 
-use crate::{Error, JsonObject, JsonValue, parse_required_header_param, Result, Signer, Verifier};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{Error, JsonObject, JsonValue, KeyMaterial, MessagePart, parse_required_header_param, Result, Signer, Verifier};
+use crate::key::Redacted;
 
 type HmacSha256 = Hmac<sha2::Sha256>;
 type HmacSha384 = Hmac<sha2::Sha384>;
@@ -11,124 +18,347 @@ type HmacSha512 = Hmac<sha2::Sha512>;
 
 /// Message verifier that supports the HMAC-SHA-256, HMAC-SHA-384 and HMAC-SHA-512 algorithms using `rust-crypto`.
 ///
-/// The wrapped key type may be anything that implements `AsRef<[u8]>`.
+/// The wrapped key type may be anything that implements [`KeyMaterial`].
 /// You can use a `Vec<u8>` to have the verifier own the key,
 /// or a `&[u8]` to prevent copying the key more than necessary.
-#[derive(Clone, Debug)]
-pub struct HmacVerifier<Key: AsRef<[u8]>> {
-	key: Key,
+/// An owned key is zeroized when the verifier is dropped.
+///
+/// Signatures are compared in constant time.
+///
+/// As required by [RFC 7518 section 3.2](https://tools.ietf.org/html/rfc7518#section-3.2),
+/// keys shorter than the hash output size are rejected with an [`Error::InvalidKey`] error.
+/// Use [`HmacVerifier::new_allow_short_key`] to accept them anyway.
+///
+/// The HMAC key setup is done once when the verifier is created,
+/// so verifying a message only hashes the message itself.
+/// The precomputed state is derived from the key, so it is zeroized when the verifier is dropped, regardless of the key type.
+#[derive(Clone)]
+pub struct HmacVerifier<Key: KeyMaterial> {
+	key   : Key,
+	label : Option<String>,
+	hs256 : Option<MacState<HmacSha256>>,
+	hs384 : Option<MacState<HmacSha384>>,
+	hs512 : Option<MacState<HmacSha512>>,
 }
 
 /// Message signer using HMAC-SHA-256.
-#[derive(Clone, Debug)]
-pub struct Hs256Signer<Key: AsRef<[u8]>> {
-	key: Key,
+///
+/// The key must be at least 32 bytes long, unless the signer is created with [`Hs256Signer::new_allow_short_key`].
+#[derive(Clone)]
+pub struct Hs256Signer<Key: KeyMaterial> {
+	key : Key,
+	mac : MacState<HmacSha256>,
 }
 
 /// Message signer using HMAC-SHA-384.
-#[derive(Clone, Debug)]
-pub struct Hs384Signer<Key: AsRef<[u8]>> {
-	key: Key,
+///
+/// The key must be at least 48 bytes long, unless the signer is created with [`Hs384Signer::new_allow_short_key`].
+#[derive(Clone)]
+pub struct Hs384Signer<Key: KeyMaterial> {
+	key : Key,
+	mac : MacState<HmacSha384>,
 }
 
 /// Message signer using HMAC-SHA-512.
-#[derive(Clone, Debug)]
-pub struct Hs512Signer<Key: AsRef<[u8]>> {
-	key: Key,
+///
+/// The key must be at least 64 bytes long, unless the signer is created with [`Hs512Signer::new_allow_short_key`].
+#[derive(Clone)]
+pub struct Hs512Signer<Key: KeyMaterial> {
+	key : Key,
+	mac : MacState<HmacSha512>,
 }
 
-impl<K: AsRef<[u8]>> HmacVerifier<K> {
+impl<K: KeyMaterial> HmacVerifier<K> {
 	/// Create a new HMAC verifier using a specified key.
-	pub fn new(key: K) -> Self {
-		Self{key}
+	///
+	/// The key must be at least 32 bytes long, the minimum for HS256.
+	/// Messages using HS384 or HS512 are rejected with an [`Error::InvalidKey`] error if the key is shorter than 48 or 64 bytes.
+	pub fn new(key: K) -> Result<Self> {
+		check_key_length(key.as_ref(), 32)?;
+		Ok(Self{
+			label : None,
+			hs256 : new_mac(key.as_ref(), false).ok().map(MacState),
+			hs384 : new_mac(key.as_ref(), false).ok().map(MacState),
+			hs512 : new_mac(key.as_ref(), false).ok().map(MacState),
+			key,
+		})
+	}
+
+	/// Create a new HMAC verifier that accepts keys shorter than the hash output size.
+	///
+	/// Short keys weaken the security of the signature.
+	/// Only use this for interoperability with legacy systems.
+	pub fn new_allow_short_key(key: K) -> Self {
+		Self{
+			label : None,
+			hs256 : new_mac(key.as_ref(), true).ok().map(MacState),
+			hs384 : new_mac(key.as_ref(), true).ok().map(MacState),
+			hs512 : new_mac(key.as_ref(), true).ok().map(MacState),
+			key,
+		}
+	}
+
+	/// Set a label to identify the key in a [`crate::VerificationReport`], like a key ID.
+	///
+	/// See [`Verifier::label`].
+	pub fn with_label(mut self, label: impl Into<String>) -> Self {
+		self.label = Some(label.into());
+		self
+	}
+
+	/// Get a precomputed MAC state, or an error if the key was too short for the algorithm.
+	fn mac<'a, M: FlatMac>(&self, mac: &'a Option<MacState<M>>) -> Result<&'a MacState<M>> {
+		match mac {
+			Some(mac) => Ok(mac),
+			None      => Err(short_key_error(self.key.as_ref(), M::OutputSize::to_usize())),
+		}
 	}
 }
 
-impl<K: AsRef<[u8]>> Hs256Signer<K> {
+impl<K: KeyMaterial> Hs256Signer<K> {
 	/// Create a HS256 signer.
-	pub fn new(key: K) -> Self {
-		Self{key}
+	///
+	/// Returns an [`Error::InvalidKey`] error if the key is shorter than 32 bytes.
+	pub fn new(key: K) -> Result<Self> {
+		let mac = new_mac(key.as_ref(), false)?;
+		Ok(Self{key, mac: MacState(mac)})
+	}
+
+	/// Create a HS256 signer that accepts keys shorter than 32 bytes.
+	///
+	/// Short keys weaken the security of the signature.
+	/// Only use this for interoperability with legacy systems.
+	pub fn new_allow_short_key(key: K) -> Self {
+		let mac = HmacSha256::new_varkey(key.as_ref()).expect("HMAC accepts keys of any length");
+		Self{key, mac: MacState(mac)}
 	}
 }
 
-impl<K: AsRef<[u8]>> Hs384Signer<K> {
+impl<K: KeyMaterial> Hs384Signer<K> {
 	/// Create a HS384 signer.
-	pub fn new(key: K) -> Self {
-		Self{key}
+	///
+	/// Returns an [`Error::InvalidKey`] error if the key is shorter than 48 bytes.
+	pub fn new(key: K) -> Result<Self> {
+		let mac = new_mac(key.as_ref(), false)?;
+		Ok(Self{key, mac: MacState(mac)})
+	}
+
+	/// Create a HS384 signer that accepts keys shorter than 48 bytes.
+	///
+	/// Short keys weaken the security of the signature.
+	/// Only use this for interoperability with legacy systems.
+	pub fn new_allow_short_key(key: K) -> Self {
+		let mac = HmacSha384::new_varkey(key.as_ref()).expect("HMAC accepts keys of any length");
+		Self{key, mac: MacState(mac)}
 	}
 }
 
-impl<K: AsRef<[u8]>> Hs512Signer<K> {
+impl<K: KeyMaterial> Hs512Signer<K> {
 	/// Create a HS512 signer.
-	pub fn new(key: K) -> Self {
-		Self{key}
+	///
+	/// Returns an [`Error::InvalidKey`] error if the key is shorter than 64 bytes.
+	pub fn new(key: K) -> Result<Self> {
+		let mac = new_mac(key.as_ref(), false)?;
+		Ok(Self{key, mac: MacState(mac)})
+	}
+
+	/// Create a HS512 signer that accepts keys shorter than 64 bytes.
+	///
+	/// Short keys weaken the security of the signature.
+	/// Only use this for interoperability with legacy systems.
+	pub fn new_allow_short_key(key: K) -> Self {
+		let mac = HmacSha512::new_varkey(key.as_ref()).expect("HMAC accepts keys of any length");
+		Self{key, mac: MacState(mac)}
+	}
+}
+
+impl<K: KeyMaterial> Drop for HmacVerifier<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> core::fmt::Debug for HmacVerifier<K> {
+	fn fmt(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+		formatter.debug_struct("HmacVerifier").field("key", &Redacted).field("label", &self.label).finish()
+	}
+}
+
+impl<K: KeyMaterial> Drop for Hs256Signer<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> core::fmt::Debug for Hs256Signer<K> {
+	fn fmt(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+		formatter.debug_struct("Hs256Signer").field("key", &Redacted).finish()
+	}
+}
+
+impl<K: KeyMaterial> Drop for Hs384Signer<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> core::fmt::Debug for Hs384Signer<K> {
+	fn fmt(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+		formatter.debug_struct("Hs384Signer").field("key", &Redacted).finish()
+	}
+}
+
+impl<K: KeyMaterial> Drop for Hs512Signer<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> core::fmt::Debug for Hs512Signer<K> {
+	fn fmt(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+		formatter.debug_struct("Hs512Signer").field("key", &Redacted).finish()
 	}
 }
 
-impl<K: AsRef<[u8]>> Verifier for HmacVerifier<K> {
+impl<K: KeyMaterial> Verifier for HmacVerifier<K> {
 	fn verify(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
-		let algorithm : &str = parse_required_header_param(protected_header, unprotected_header, "alg")?;
+		let result = parse_required_header_param(protected_header, unprotected_header, "alg").and_then(|algorithm: &str| {
+			match algorithm {
+				"HS256" => self.mac(&self.hs256)?.verify(encoded_header, encoded_payload, signature),
+				"HS384" => self.mac(&self.hs384)?.verify(encoded_header, encoded_payload, signature),
+				"HS512" => self.mac(&self.hs512)?.verify(encoded_header, encoded_payload, signature),
+				_       => Err(Error::unsupported_mac_algorithm(algorithm.to_string()).with_header_param("alg")),
+			}.map_err(|e| e.with_algorithm(algorithm))
+		});
 
-		match algorithm {
-			"HS256" => verify_mac(encoded_header, encoded_payload, signature, HmacSha256::new_varkey(self.key.as_ref()).unwrap()),
-			"HS384" => verify_mac(encoded_header, encoded_payload, signature, HmacSha384::new_varkey(self.key.as_ref()).unwrap()),
-			"HS512" => verify_mac(encoded_header, encoded_payload, signature, HmacSha512::new_varkey(self.key.as_ref()).unwrap()),
-			_       => Err(Error::unsupported_mac_algorithm(algorithm.to_string())),
-		}
+		#[cfg(feature = "tracing")]
+		crate::trace::verifier_outcome("HmacVerifier", self.label(), protected_header, unprotected_header, result.as_ref().map(|_| ()));
+		result
+	}
+
+	fn label(&self) -> Option<&str> {
+		self.label.as_deref()
 	}
 }
 
-impl<K: AsRef<[u8]>> Signer for Hs256Signer<K> {
+impl<K: KeyMaterial> Signer for Hs256Signer<K> {
 	fn set_header_params(&self, header: &mut JsonObject) {
 		header.insert("alg".to_string(), JsonValue::from("HS256"));
 	}
 
 	fn compute_mac(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>> {
-		let hmac = HmacSha256::new_varkey(self.key.as_ref()).unwrap();
-		Ok(compute_mac(encoded_header, encoded_payload, hmac).into_bytes().as_slice().to_owned())
+		Ok(self.mac.compute(encoded_header, encoded_payload).into_bytes().to_vec())
 	}
 }
 
-impl<K: AsRef<[u8]>> Signer for Hs384Signer<K> {
+impl<K: KeyMaterial> Signer for Hs384Signer<K> {
 	fn set_header_params(&self, header: &mut JsonObject) {
 		header.insert("alg".to_string(), JsonValue::from("HS384"));
 	}
 
 	fn compute_mac(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>> {
-		let hmac = HmacSha384::new_varkey(self.key.as_ref()).unwrap();
-		Ok(compute_mac(encoded_header, encoded_payload, hmac).into_bytes().as_slice().to_owned())
+		Ok(self.mac.compute(encoded_header, encoded_payload).into_bytes().to_vec())
 	}
 }
 
-impl<K: AsRef<[u8]>> Signer for Hs512Signer<K> {
+impl<K: KeyMaterial> Signer for Hs512Signer<K> {
 	fn set_header_params(&self, header: &mut JsonObject) {
 		header.insert("alg".to_string(), JsonValue::from("HS512"));
 	}
 
 	fn compute_mac(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>> {
-		let hmac = HmacSha512::new_varkey(self.key.as_ref()).unwrap();
-		Ok(compute_mac(encoded_header, encoded_payload, hmac).into_bytes().as_slice().to_owned())
+		Ok(self.mac.compute(encoded_header, encoded_payload).into_bytes().to_vec())
+	}
+}
+
+/// Compute the HMAC of a complete JWS signing input for a HMAC-SHA2 algorithm.
+pub(crate) fn compute_hmac(algorithm: &str, key: &[u8], signing_input: &[u8]) -> Result<Vec<u8>> {
+	fn compute<M: FlatMac + NewMac>(key: &[u8], signing_input: &[u8]) -> Result<Vec<u8>> {
+		let mut mac = MacState(new_mac::<M>(key, false)?);
+		mac.0.update(signing_input);
+		Ok(mac.0.finalize_reset().into_bytes().to_vec())
+	}
+
+	match algorithm {
+		"HS256" => compute::<HmacSha256>(key, signing_input),
+		"HS384" => compute::<HmacSha384>(key, signing_input),
+		"HS512" => compute::<HmacSha512>(key, signing_input),
+		_       => Err(Error::unsupported_mac_algorithm(algorithm.to_string())),
+	}.map_err(|e| e.with_algorithm(algorithm))
+}
+
+/// Create a MAC with a key, checking that the key is at least as long as the MAC output.
+fn new_mac<M: Mac + NewMac>(key: &[u8], allow_short_key: bool) -> Result<M> {
+	if !allow_short_key {
+		check_key_length(key, M::OutputSize::to_usize())?;
+	}
+	M::new_varkey(key).map_err(|_| Error::invalid_key("invalid HMAC key"))
+}
+
+/// Check that a HMAC key has at least the given length.
+fn check_key_length(key: &[u8], min_length: usize) -> Result<()> {
+	if key.len() < min_length {
+		Err(short_key_error(key, min_length))
+	} else {
+		Ok(())
 	}
 }
 
-/// Feed the encoded header and payload to a MAC in the proper format.
+fn short_key_error(key: &[u8], min_length: usize) -> Error {
+	Error::invalid_key(format!("HMAC key must be at least {} bytes, got {} bytes", min_length, key.len()))
+}
+
+/// Feed the encoded header and payload to a freshly keyed MAC in the proper format.
 fn feed_mac(encoded_header: &[u8], encoded_payload: &[u8], mac: &mut impl Mac) {
-	mac.reset();
 	mac.update(encoded_header);
 	mac.update(b".");
 	mac.update(encoded_payload);
 }
 
-/// Compute the Message Authentication Code for the MAC function.
-fn compute_mac<M: Mac>(encoded_header: &[u8], encoded_payload: &[u8], mut mac: M) -> crypto_mac::Output<M> {
-	feed_mac(encoded_header, encoded_payload, &mut mac);
-	mac.finalize()
+/// MAC types that hold all their state inline, without pointers to other memory.
+///
+/// # Safety
+/// Implementations must be valid for [`zeroize::zeroize_flat_type`]:
+/// the type must not own heap memory or other resources that need to be released when dropped.
+unsafe trait FlatMac: Mac + Clone {}
+
+// The HMAC state consists of the digest states and the padded key, all stored in fixed size arrays.
+unsafe impl FlatMac for HmacSha256 {}
+unsafe impl FlatMac for HmacSha384 {}
+unsafe impl FlatMac for HmacSha512 {}
+
+/// A keyed MAC state that is zeroized when dropped.
+///
+/// The state contains the padded key, so it must be wiped just like the key itself.
+#[derive(Clone)]
+struct MacState<M: FlatMac>(M);
+
+impl<M: FlatMac> Drop for MacState<M> {
+	fn drop(&mut self) {
+		// SAFETY: FlatMac guarantees that the type can be wiped as plain memory, and it is not used after being dropped.
+		unsafe { zeroize::zeroize_flat_type(&mut self.0) }
+	}
 }
 
-/// Verify the signature of a JWS Compact Serialization message.
-fn verify_mac<M: Mac>(encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8], mut mac: M) -> Result<()> {
-	feed_mac(encoded_header, encoded_payload, &mut mac);
-	mac.verify(signature).map_err(|_| Error::invalid_signature(""))
+impl<M: FlatMac> MacState<M> {
+	/// Compute the Message Authentication Code for the encoded header and payload.
+	///
+	/// The computation uses a copy of the state, which is zeroized afterwards.
+	fn compute(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> crypto_mac::Output<M> {
+		let mut mac = self.clone();
+		feed_mac(encoded_header, encoded_payload, &mut mac.0);
+		mac.0.finalize_reset()
+	}
+
+	/// Verify the signature of a JWS Compact Serialization message in constant time.
+	fn verify(&self, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
+		let expected = self.compute(encoded_header, encoded_payload);
+		if signature.len() == M::OutputSize::to_usize() && expected == crypto_mac::Output::new(GenericArray::clone_from_slice(signature)) {
+			Ok(())
+		} else {
+			Err(Error::invalid_signature("").with_part(MessagePart::Signature))
+		}
+	}
 }
 
 #[cfg(test)]
@@ -160,7 +390,7 @@ mod test {
 
 	#[test]
 	fn test_decode_verify() {
-		let message = compact::decode_verify(RFC7515_A1_ENCODED, &HmacVerifier::new(RFC7515_A1_KEY)).unwrap();
+		let message = compact::decode_verify(RFC7515_A1_ENCODED, &HmacVerifier::new(RFC7515_A1_KEY).unwrap()).unwrap();
 
 		assert!(&message.header == &json_object!{
 			"alg": "HS256",
@@ -177,20 +407,38 @@ mod test {
 
 	#[test]
 	fn test_decode_verify_invalid() {
-		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(RFC7515_A1_ENCODED_MANGLED, &HmacVerifier::new(RFC7515_A1_KEY)));
+		let error = compact::decode_verify(RFC7515_A1_ENCODED_MANGLED, &HmacVerifier::new(RFC7515_A1_KEY).unwrap()).unwrap_err();
+		assert!(error.kind() == Error::InvalidSignature);
+		assert!(error.part() == Some(MessagePart::Signature));
+		assert!(error.algorithm() == Some("HS256"));
+
+		let error = HmacVerifier::new(RFC7515_A1_KEY).unwrap().verify(Some(&json_object!{"alg": "HS1"}), None, b"", b"", b"").unwrap_err();
+		assert!(error.kind() == Error::UnsupportedMacAlgorithm);
+		assert!(error.header_param() == Some("alg"));
+		assert!(error.algorithm() == Some("HS1"));
+	
+		// Truncated and extended signatures are rejected.
+		let parts : Vec<&[u8]> = RFC7515_A1_ENCODED.split(|&x| x == b'.').collect();
+		let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).unwrap();
+		let verifier  = HmacVerifier::new(RFC7515_A1_KEY).unwrap();
+		let header    = json_object!{"alg": "HS256"};
+		assert!(let Ok(()) = verifier.verify(Some(&header), None, parts[0], parts[1], &signature));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = verifier.verify(Some(&header), None, parts[0], parts[1], &signature[..31]));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = verifier.verify(Some(&header), None, parts[0], parts[1], &[&signature[..], b"x"].concat()));
 	}
 
 	#[test]
 	fn test_encode_sign_hmac_sha2() {
+		// The expected encodings below were computed with a short key.
 		let header       = json_object!{"typ": "JWT"};
-		let signed_hs256 = compact::encode_sign(header.clone(), b"foo", &Hs256Signer::new(b"secretkey")).expect("sign HS256 failed");
-		let signed_hs384 = compact::encode_sign(header.clone(), b"foo", &Hs384Signer::new(b"secretkey")).expect("sign HS384 failed");
-		let signed_hs512 = compact::encode_sign(header.clone(), b"foo", &Hs512Signer::new(b"secretkey")).expect("sign HS512 failed");
+		let signed_hs256 = compact::encode_sign(header.clone(), b"foo", &Hs256Signer::new_allow_short_key(b"secretkey")).expect("sign HS256 failed");
+		let signed_hs384 = compact::encode_sign(header.clone(), b"foo", &Hs384Signer::new_allow_short_key(b"secretkey")).expect("sign HS384 failed");
+		let signed_hs512 = compact::encode_sign(header.clone(), b"foo", &Hs512Signer::new_allow_short_key(b"secretkey")).expect("sign HS512 failed");
 
 		// Test that the signed message can be decoded and verified with the right key.
-		let decoded_hs256 = compact::decode_verify(signed_hs256.as_bytes(), &HmacVerifier::new(&b"secretkey"[..])).expect("decode_verify HS256 failed");
-		let decoded_hs384 = compact::decode_verify(signed_hs384.as_bytes(), &HmacVerifier::new(&b"secretkey"[..])).expect("decode_verify HS384 failed");
-		let decoded_hs512 = compact::decode_verify(signed_hs512.as_bytes(), &HmacVerifier::new(&b"secretkey"[..])).expect("decode_verify HS512 failed");
+		let decoded_hs256 = compact::decode_verify(signed_hs256.as_bytes(), &HmacVerifier::new_allow_short_key(&b"secretkey"[..])).expect("decode_verify HS256 failed");
+		let decoded_hs384 = compact::decode_verify(signed_hs384.as_bytes(), &HmacVerifier::new_allow_short_key(&b"secretkey"[..])).expect("decode_verify HS384 failed");
+		let decoded_hs512 = compact::decode_verify(signed_hs512.as_bytes(), &HmacVerifier::new_allow_short_key(&b"secretkey"[..])).expect("decode_verify HS512 failed");
 
 		// Test that the decoded payload is still correct.
 		assert!(decoded_hs256.payload == b"foo");
@@ -203,13 +451,38 @@ mod test {
 		assert!(&decoded_hs512.header == &json_object!{"typ": "JWT", "alg": "HS512"});
 
 		// Test that the signed message can not be verified with a wrong key.
-		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed_hs256.as_bytes(), &HmacVerifier::new(&b"notthekey"[..])));
-		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed_hs384.as_bytes(), &HmacVerifier::new(&b"notthekey"[..])));
-		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed_hs512.as_bytes(), &HmacVerifier::new(&b"notthekey"[..])));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed_hs256.as_bytes(), &HmacVerifier::new_allow_short_key(&b"notthekey"[..])));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed_hs384.as_bytes(), &HmacVerifier::new_allow_short_key(&b"notthekey"[..])));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(signed_hs512.as_bytes(), &HmacVerifier::new_allow_short_key(&b"notthekey"[..])));
 
 		// Also test the raw encoded form, although that's not really part of the API guarantee.
 		assert!(signed_hs256.data() == "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.Zm9v.4o4hfsHG_tN4bMqxCi0CYt-OArTTogFmgZuN54HS7ZY");
 		assert!(signed_hs384.data() == "eyJhbGciOiJIUzM4NCIsInR5cCI6IkpXVCJ9.Zm9v.OoAr5wyN5KnBRY0OFYCqsk1mHrxuR_Lot33HVV43udouF1wlD1lvXL2oINrGU-9v");
 		assert!(signed_hs512.data() == "eyJhbGciOiJIUzUxMiIsInR5cCI6IkpXVCJ9.Zm9v.Al1_vJpGnm78IRKDm48NkAoYkpR4KE1hA5jN09_QnGktPKgP4QB7MJnXgeXuC5E6BVlOp7oaR-FSphbq206vxA");
 	}
+
+	#[test]
+	fn test_short_key() {
+		// Short keys are rejected when the signer or verifier is created.
+		let key = [0u8; 48];
+		assert!(let Ok(_) = Hs256Signer::new(&key));
+		assert!(let Ok(_) = Hs384Signer::new(&key));
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = Hs512Signer::new(&key));
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = Hs256Signer::new(b""));
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = HmacVerifier::new(&key[..31]));
+
+		// A verifier key that is long enough for HS256 can still be too short for HS512.
+		let signed = compact::encode_sign(json_object!{}, b"foo", &Hs512Signer::new_allow_short_key(&key)).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = compact::decode_verify(signed.as_bytes(), &HmacVerifier::new(&key).unwrap()));
+		assert!(let Ok(_) = compact::decode_verify(signed.as_bytes(), &HmacVerifier::new_allow_short_key(&key)));
+	}
+
+	#[test]
+	fn test_debug_redacts_key() {
+		assert!(format!("{:?}", HmacVerifier::new_allow_short_key(b"secretkey".to_vec())) == "HmacVerifier { key: <redacted>, label: None }");
+		assert!(format!("{:?}", HmacVerifier::new_allow_short_key(b"secretkey".to_vec()).with_label("key-1")) == r#"HmacVerifier { key: <redacted>, label: Some("key-1") }"#);
+		assert!(format!("{:?}", Hs256Signer::new_allow_short_key(b"secretkey")) == "Hs256Signer { key: <redacted> }");
+		assert!(format!("{:?}", Hs384Signer::new_allow_short_key("secretkey")) == "Hs384Signer { key: <redacted> }");
+		assert!(format!("{:?}", Hs512Signer::new_allow_short_key(String::from("secretkey"))) == "Hs512Signer { key: <redacted> }");
+	}
 }
@@ -0,0 +1,403 @@
+//! Detached signatures for HTTP messages in the Open Banking `x-jws-signature` profile.
+//!
+//! The body of a request or response is signed as a JWS with detached ([RFC 7515 appendix F](https://tools.ietf.org/html/rfc7515#appendix-F))
+//! and unencoded ([RFC 7797](https://tools.ietf.org/html/rfc7797)) payload, as required by the Open Banking and FAPI message signing profile.
+//! The signature is added to the message in the [`SIGNATURE_HEADER`] header as `<protected header>..<signature>`.
+//! The raw body is the payload, so the signing input is the encoded protected header, a period and the body.
+//!
+//! The protected header contains `"b64": false` and the Open Banking claims [`IAT_PARAM`], [`ISS_PARAM`] and [`TAN_PARAM`].
+//! All of them are listed in the `crit` header parameter,
+//! so that receivers that don't understand them reject the signature instead of ignoring it.
+//! Only the body is signed: the method, path and other headers are not covered by the signature.
+//!
+//! Messages are represented with the types from the [`http`](https://docs.rs/http) crate,
+//! so this module can be used with any HTTP client or server framework based on it.
+//! The body can be any type that implements `AsRef<[u8]>`.
+//! The signing and verification of every message is reported to the global [`crate::observer::Observer`], if any.
+//!
+//! This module is only available with the `http` feature.
+//!
+//! # Example:
+//! ```
+//! use jws::JsonObject;
+//! use jws::hmac::{HmacVerifier, Hs256Signer};
+//! use jws::http_signature::{sign_request, verify_request, SignatureClaims};
+//!
+//! fn sign_verify() -> jws::Result<()> {
+//!   let key = b"this is a secret key that is long enough for all HMAC algorithms";
+//!   let mut request = http::Request::post("/payments")
+//!     .header("content-type", "application/json")
+//!     .body(br#"{"amount": "10.00"}"#.to_vec())
+//!     .unwrap();
+//!
+//!   // Sign the request body, which adds the x-jws-signature header.
+//!   let claims = SignatureClaims::new("0015800001041RHAAY/acme-payments", "openbanking.org.uk");
+//!   sign_request(&mut request, JsonObject::new(), &claims, &Hs256Signer::new(key)?)?;
+//!
+//!   // Verify the request, and check the claims of the signature.
+//!   let message = verify_request(&request, &HmacVerifier::new(key)?)?;
+//!   assert_eq!(SignatureClaims::from_header(&message.header)?.issuer, "0015800001041RHAAY/acme-payments");
+//!   Ok(())
+//! }
+//! # sign_verify().unwrap();
+//! ```
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::{HeaderMap, HeaderValue, Request, Response};
+
+use crate::compact::{self, decode_base64_url, decode_json, DecodedMessage};
+use crate::observer::{Observation, Operation};
+use crate::{Error, JsonObject, JsonValue, MessagePart, parse_required_header_param, Result, Signer, Verifier};
+
+/// The name of the HTTP header that holds the detached signature.
+pub const SIGNATURE_HEADER : &str = "x-jws-signature";
+
+/// The protected header parameter with the time the signature was created, in seconds since the Unix epoch.
+pub const IAT_PARAM : &str = "http://openbanking.org.uk/iat";
+
+/// The protected header parameter with the issuer of the signature.
+pub const ISS_PARAM : &str = "http://openbanking.org.uk/iss";
+
+/// The protected header parameter with the trust anchor for the signing key.
+pub const TAN_PARAM : &str = "http://openbanking.org.uk/tan";
+
+/// The header parameters that are listed in the `crit` header parameter.
+pub const CRITICAL_PARAMS : [&str; 4] = ["b64", IAT_PARAM, ISS_PARAM, TAN_PARAM];
+
+/// The Open Banking claims in the protected header of a signature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignatureClaims {
+	/// The time the signature was created, in seconds since the Unix epoch.
+	pub issued_at    : u64,
+
+	/// The issuer of the signature.
+	pub issuer       : String,
+
+	/// The trust anchor for the signing key, like `openbanking.org.uk`.
+	pub trust_anchor : String,
+}
+
+impl SignatureClaims {
+	/// Create claims for a signature issued now.
+	pub fn new(issuer: impl Into<String>, trust_anchor: impl Into<String>) -> Self {
+		let issued_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+		Self{issued_at, issuer: issuer.into(), trust_anchor: trust_anchor.into()}
+	}
+
+	/// Parse the claims from the protected header of a signature.
+	pub fn from_header(header: &JsonObject) -> Result<Self> {
+		Ok(Self {
+			issued_at    : parse_required_header_param(Some(header), None, IAT_PARAM)?,
+			issuer       : parse_required_header_param(Some(header), None, ISS_PARAM)?,
+			trust_anchor : parse_required_header_param(Some(header), None, TAN_PARAM)?,
+		})
+	}
+}
+
+/// Sign the body of a request and add the detached signature to the [`SIGNATURE_HEADER`] header.
+///
+/// The `header` can hold additional protected header parameters, like `kid` or `typ`.
+/// See [`detached_signature`] for the parameters that are added to it.
+pub fn sign_request<B: AsRef<[u8]>>(request: &mut Request<B>, header: JsonObject, claims: &SignatureClaims, signer: &impl Signer) -> Result<()> {
+	let signature = detached_signature(header, claims, request.body().as_ref(), signer)?;
+	insert_signature(request.headers_mut(), &signature)
+}
+
+/// Sign the body of a response and add the detached signature to the [`SIGNATURE_HEADER`] header.
+///
+/// See [`sign_request`].
+pub fn sign_response<B: AsRef<[u8]>>(response: &mut Response<B>, header: JsonObject, claims: &SignatureClaims, signer: &impl Signer) -> Result<()> {
+	let signature = detached_signature(header, claims, response.body().as_ref(), signer)?;
+	insert_signature(response.headers_mut(), &signature)
+}
+
+/// Create a detached signature for a body, without adding it to a message.
+///
+/// The claims, `"b64": false` and the `crit` header parameter are added to the protected header,
+/// before the signer sets its own header parameters.
+/// The signature is returned as `<protected header>..<signature>`.
+pub fn detached_signature(header: JsonObject, claims: &SignatureClaims, body: &[u8], signer: &impl Signer) -> Result<String> {
+	let mut header = header;
+	header.insert("b64".to_string(), JsonValue::from(false));
+	header.insert(IAT_PARAM.to_string(), JsonValue::from(claims.issued_at));
+	header.insert(ISS_PARAM.to_string(), JsonValue::from(claims.issuer.as_str()));
+	header.insert(TAN_PARAM.to_string(), JsonValue::from(claims.trust_anchor.as_str()));
+	header.insert("crit".to_string(), JsonValue::from(CRITICAL_PARAMS.to_vec()));
+	sign_unencoded(header, body, signer)
+}
+
+/// Verify the detached signature in the [`SIGNATURE_HEADER`] header of a request.
+///
+/// See [`verify_detached_signature`].
+pub fn verify_request<B: AsRef<[u8]>>(request: &Request<B>, verifier: &impl Verifier) -> Result<DecodedMessage> {
+	verify_detached_signature(signature_header(request.headers())?, request.body().as_ref(), verifier)
+}
+
+/// Verify the detached signature in the [`SIGNATURE_HEADER`] header of a response.
+///
+/// See [`verify_detached_signature`].
+pub fn verify_response<B: AsRef<[u8]>>(response: &Response<B>, verifier: &impl Verifier) -> Result<DecodedMessage> {
+	verify_detached_signature(signature_header(response.headers())?, response.body().as_ref(), verifier)
+}
+
+/// Verify a detached signature over a body.
+///
+/// The protected header must have `"b64": false` and the Open Banking claims,
+/// and the `crit` header parameter must list exactly the [`CRITICAL_PARAMS`],
+/// or an [`Error::InvalidHeaderParam`] or [`Error::MissingHeaderParam`] error is returned.
+/// Checking the values of the claims, like the issuer and the age of the signature, is left to the caller.
+///
+/// On success, the decoded message is returned with the protected header and the body as payload.
+pub fn verify_detached_signature(signature: &str, body: &[u8], verifier: &impl Verifier) -> Result<DecodedMessage> {
+	let observation = Observation::start(Operation::Verify);
+	let result = verify_unencoded(signature, body, verifier);
+	observation.finish(result.as_ref().ok().map(|message| &message.header), result.as_ref().map(|_| ()));
+	result
+}
+
+/// Sign a body as unencoded, detached payload.
+fn sign_unencoded(header: JsonObject, body: &[u8], signer: &impl Signer) -> Result<String> {
+	let observation = Observation::start(Operation::Sign);
+	let mut header = header;
+	signer.set_header_params(&mut header);
+
+	// Serializing header can't fail since it's already a JSON object.
+	let encoded_header = base64::encode_config(serde_json::to_vec(&header).unwrap(), base64::URL_SAFE_NO_PAD);
+	let result = signer.compute_mac(encoded_header.as_bytes(), body)
+		.map(|signature| format!("{}..{}", encoded_header, base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)));
+	observation.finish(Some(&header), result.as_ref().map(|_| ()));
+	result
+}
+
+/// Verify a signature over a body as unencoded, detached payload.
+fn verify_unencoded(signature: &str, body: &[u8], verifier: &impl Verifier) -> Result<DecodedMessage> {
+	let parts = compact::split_encoded_parts(signature.as_bytes())?;
+	if !parts.payload.is_empty() {
+		return Err(Error::invalid_message("signature does not have a detached payload").with_part(MessagePart::Payload));
+	}
+
+	let header : JsonObject = decode_json(&decode_base64_url(parts.header, MessagePart::Header)?, MessagePart::Header)?;
+	check_critical_params(&header)?;
+	let b64 : bool = parse_required_header_param(Some(&header), None, "b64")?;
+	if b64 {
+		return Err(Error::invalid_header_param("payload must be unencoded").with_header_param("b64"));
+	}
+	SignatureClaims::from_header(&header)?;
+
+	let signature = decode_base64_url(parts.signature, MessagePart::Signature)?;
+	verifier.verify(Some(&header), None, parts.header, body, &signature)?;
+	Ok(DecodedMessage{header, payload: body.to_vec()})
+}
+
+/// Check that the `crit` header parameter lists exactly the [`CRITICAL_PARAMS`].
+///
+/// See [RFC 7515 section 4.1.11](https://tools.ietf.org/html/rfc7515#section-4.1.11).
+fn check_critical_params(header: &JsonObject) -> Result<()> {
+	let critical : Vec<String> = parse_required_header_param(Some(header), None, "crit")?;
+	if let Some(name) = critical.iter().find(|name| !CRITICAL_PARAMS.contains(&name.as_str())) {
+		return Err(Error::invalid_header_param(format!("unsupported critical header parameter {}", name)).with_header_param("crit"));
+	}
+	if let Some(name) = CRITICAL_PARAMS.iter().find(|name| !critical.iter().any(|critical| critical == *name)) {
+		return Err(Error::invalid_header_param(format!("{} is not marked as critical", name)).with_header_param("crit"));
+	}
+	Ok(())
+}
+
+/// Get the value of the [`SIGNATURE_HEADER`] header.
+fn signature_header(headers: &HeaderMap) -> Result<&str> {
+	let signature = headers.get(SIGNATURE_HEADER)
+		.ok_or_else(|| Error::invalid_message(format!("message has no {} header", SIGNATURE_HEADER)))?;
+	signature.to_str().map_err(|_| Error::invalid_message(format!("invalid {} header", SIGNATURE_HEADER)))
+}
+
+/// Set the [`SIGNATURE_HEADER`] header.
+fn insert_signature(headers: &mut HeaderMap, signature: &str) -> Result<()> {
+	let signature = HeaderValue::from_str(signature).map_err(|e| Error::other(format!("failed to create {} header: {}", SIGNATURE_HEADER, e)))?;
+	headers.insert(SIGNATURE_HEADER, signature);
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+	use crate::json_object;
+	use crate::jwk::Jwk;
+
+	use assert2::assert;
+
+	const KEY : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+
+	fn request() -> Request<Vec<u8>> {
+		Request::post("/payments?dry-run=1")
+			.header("Content-Type", "application/json")
+			.body(br#"{"amount": "10.00"}"#.to_vec())
+			.unwrap()
+	}
+
+	fn claims() -> SignatureClaims {
+		SignatureClaims{issued_at: 1501497671, issuer: String::from("C=UK, ST=England, L=London, O=Acme Ltd."), trust_anchor: String::from("openbanking.org.uk")}
+	}
+
+	// Example taken from RFC 7797 section 4.2
+	// https://tools.ietf.org/html/rfc7797#section-4.2
+	//
+	// Key and algorithm are the same as in RFC 7515 appendix A.1, but the payload is unencoded and detached.
+	//  Key: AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow
+	//  Payload: $.02
+	const RFC7797_KEY       : &str = "AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow";
+	const RFC7797_SIGNATURE : &str = "eyJhbGciOiJIUzI1NiIsImI2NCI6ZmFsc2UsImNyaXQiOlsiYjY0Il19..A5dxf2s96_n5FLueVuW1Z_vh161FwXZC4YLPff6dmDY";
+
+	#[test]
+	fn test_rfc7797_section_4_2() {
+		let key = base64::decode_config(RFC7797_KEY, base64::URL_SAFE_NO_PAD).unwrap();
+		let signature = sign_unencoded(json_object!{"b64": false, "crit": vec!["b64"]}, b"$.02", &Hs256Signer::new(&key).unwrap()).unwrap();
+		assert!(signature == RFC7797_SIGNATURE);
+
+		// The signature lacks the Open Banking claims, so it is rejected by the profile.
+		let error = verify_detached_signature(RFC7797_SIGNATURE, b"$.02", &HmacVerifier::new(&key).unwrap()).unwrap_err();
+		assert!(error.kind() == Error::InvalidHeaderParam);
+		assert!(error.header_param() == Some("crit"));
+	}
+
+	#[test]
+	fn test_sign_verify_request() {
+		let mut request = request();
+		sign_request(&mut request, json_object!{"kid": "90210ABAD"}, &claims(), &Hs256Signer::new(KEY).unwrap()).unwrap();
+
+		let signature = request.headers()[SIGNATURE_HEADER].to_str().unwrap();
+		assert!(signature.contains(".."));
+
+		let message = verify_request(&request, &HmacVerifier::new(KEY).unwrap()).unwrap();
+		assert!(message.header == json_object!{
+			"alg": "HS256",
+			"kid": "90210ABAD",
+			"b64": false,
+			"http://openbanking.org.uk/iat": 1501497671,
+			"http://openbanking.org.uk/iss": "C=UK, ST=England, L=London, O=Acme Ltd.",
+			"http://openbanking.org.uk/tan": "openbanking.org.uk",
+			"crit": vec!["b64", "http://openbanking.org.uk/iat", "http://openbanking.org.uk/iss", "http://openbanking.org.uk/tan"],
+		});
+		assert!(message.payload == request.body().as_slice());
+		assert!(SignatureClaims::from_header(&message.header).unwrap() == claims());
+	}
+
+	#[test]
+	fn test_sign_verify_response() {
+		let mut response = Response::new(br#"{"Data": {}}"#.to_vec());
+		sign_response(&mut response, JsonObject::new(), &claims(), &Hs256Signer::new(KEY).unwrap()).unwrap();
+		assert!(let Ok(_) = verify_response(&response, &HmacVerifier::new(KEY).unwrap()));
+
+		*response.body_mut() = br#"{"Data": {"Status": "Rejected"}}"#.to_vec();
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = verify_response(&response, &HmacVerifier::new(KEY).unwrap()));
+	}
+
+	#[test]
+	fn test_verify_tampered_request() {
+		let mut signed = request();
+		sign_request(&mut signed, JsonObject::new(), &claims(), &Hs256Signer::new(KEY).unwrap()).unwrap();
+		let verifier = HmacVerifier::new(KEY).unwrap();
+
+		let mut request = signed.clone();
+		*request.body_mut() = br#"{"amount": "1000.00"}"#.to_vec();
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = verify_request(&request, &verifier));
+
+		// The signature covers only the body.
+		let mut request = signed.clone();
+		*request.method_mut() = http::Method::PUT;
+		assert!(let Ok(_) = verify_request(&request, &verifier));
+
+		let mut request = signed.clone();
+		let signature = request.headers()[SIGNATURE_HEADER].to_str().unwrap().replacen("..", ".Zm9v.", 1);
+		request.headers_mut().insert(SIGNATURE_HEADER, HeaderValue::from_str(&signature).unwrap());
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = verify_request(&request, &verifier));
+
+		let mut request = signed;
+		request.headers_mut().remove(SIGNATURE_HEADER);
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = verify_request(&request, &verifier));
+	}
+
+	#[test]
+	fn test_verify_critical_params() {
+		let body = request().into_body();
+		let verifier = HmacVerifier::new(KEY).unwrap();
+		let sign = |header: JsonObject| sign_unencoded(header, &body, &Hs256Signer::new(KEY).unwrap()).unwrap();
+		let header = || json_object!{
+			"b64": false,
+			"http://openbanking.org.uk/iat": 1501497671,
+			"http://openbanking.org.uk/iss": "C=UK, ST=England, L=London, O=Acme Ltd.",
+			"http://openbanking.org.uk/tan": "openbanking.org.uk",
+		};
+		let with = |name: &str, value: JsonValue| {
+			let mut header = header();
+			header.insert(name.to_string(), value);
+			header
+		};
+
+		// The order of the critical parameters does not matter.
+		let signature = sign(with("crit", JsonValue::from(vec![TAN_PARAM, ISS_PARAM, IAT_PARAM, "b64"])));
+		assert!(let Ok(_) = verify_detached_signature(&signature, &body, &verifier));
+
+		let signature = sign(header());
+		assert!(let Err(Error { kind: Error::MissingHeaderParam, .. }) = verify_detached_signature(&signature, &body, &verifier));
+
+		let signature = sign(with("crit", JsonValue::from(vec!["b64", IAT_PARAM, ISS_PARAM])));
+		assert!(let Err(Error { kind: Error::InvalidHeaderParam, .. }) = verify_detached_signature(&signature, &body, &verifier));
+
+		let mut header = with("crit", JsonValue::from(vec!["b64", IAT_PARAM, ISS_PARAM, TAN_PARAM, "exp"]));
+		header.insert("exp".to_string(), JsonValue::from(0));
+		let error = verify_detached_signature(&sign(header), &body, &verifier).unwrap_err();
+		assert!(error.kind() == Error::InvalidHeaderParam);
+		assert!(error.header_param() == Some("crit"));
+
+		// The payload must be unencoded, and the claims must be present.
+		let mut header = with("crit", JsonValue::from(CRITICAL_PARAMS.to_vec()));
+		header.insert("b64".to_string(), JsonValue::from(true));
+		let error = verify_detached_signature(&sign(header), &body, &verifier).unwrap_err();
+		assert!(error.header_param() == Some("b64"));
+
+		let mut header = with("crit", JsonValue::from(CRITICAL_PARAMS.to_vec()));
+		header.remove(TAN_PARAM);
+		assert!(let Err(Error { kind: Error::MissingHeaderParam, .. }) = verify_detached_signature(&sign(header), &body, &verifier));
+	}
+
+	#[test]
+	fn test_verify_ps256_with_jwk() {
+		use ::rsa::sha2::{Digest, Sha256};
+		use ::rsa::traits::PublicKeyParts;
+
+		/// Signer for PS256, as required by the Open Banking profile.
+		struct Ps256Signer(::rsa::RsaPrivateKey);
+
+		impl Signer for Ps256Signer {
+			fn set_header_params(&self, header: &mut JsonObject) {
+				header.insert("alg".to_string(), JsonValue::from("PS256"));
+			}
+
+			fn compute_mac(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>> {
+				let hash = Sha256::digest([encoded_header, b".", encoded_payload].concat());
+				Ok(self.0.sign_with_rng(&mut rand_core::OsRng, ::rsa::Pss::new::<Sha256>(), &hash).unwrap())
+			}
+		}
+
+		let key = ::rsa::RsaPrivateKey::new(&mut rand_core::OsRng, 1024).unwrap();
+		let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+		let jwk : Jwk = serde_json::from_value(serde_json::json!({
+			"kty": "RSA", "kid": "90210ABAD", "use": "sig", "alg": "PS256", "n": encode(&key.n().to_bytes_be()), "e": encode(&key.e().to_bytes_be()),
+		})).unwrap();
+
+		let mut request = request();
+		sign_request(&mut request, json_object!{"kid": "90210ABAD"}, &claims(), &Ps256Signer(key)).unwrap();
+		let message = verify_request(&request, &jwk.verifier()).unwrap();
+		assert!(message.header.get("alg") == Some(&JsonValue::from("PS256")));
+	}
+
+	#[test]
+	fn test_signature_claims_new() {
+		let claims = SignatureClaims::new("issuer", "openbanking.org.uk");
+		assert!(claims.issued_at > 1501497671);
+		assert!(claims.issuer == "issuer");
+		assert!(claims.trust_anchor == "openbanking.org.uk");
+	}
+}
@@ -0,0 +1,439 @@
+//! JWS JSON Serialization with multiple signatures.
+//!
+//! The general JWS JSON Serialization as described in [RFC 7515 section 7.2.1](https://tools.ietf.org/html/rfc7515#section-7.2.1)
+//! can hold any number of signatures over the same payload.
+//! This allows a message to be signed with multiple keys, for example to support clients that only know one of the algorithms.
+//!
+//! Combine signers with [`Signer::and`](crate::Signer::and) and use [`encode_sign`] to add one signature per signer.
+//! Use [`decode_verify`] to accept a message with at least one valid signature,
+//! or [`decode_verify_threshold`] to require signatures from a minimum number of distinct keys (k-of-n).
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::compact::{decode_base64_url, decode_json};
+use crate::{Error, JsonObject, MessagePart, MultiSigner, Result, ThresholdVerifier, Verifier};
+
+#[cfg(feature = "std")]
+use crate::observer::{Observation, Operation};
+
+/// A decoded message in the JWS JSON Serialization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedJsonMessage {
+	/// The decoded payload.
+	pub payload    : Vec<u8>,
+
+	/// The decoded signatures.
+	///
+	/// For [`decode_verify`], these are only the signatures that were verified successfully.
+	pub signatures : Vec<DecodedSignature>,
+}
+
+/// A decoded signature of a message in the JWS JSON Serialization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedSignature {
+	/// The position of the signature in the message.
+	pub index              : usize,
+
+	/// The protected header of the signature, if any.
+	pub protected_header   : Option<JsonObject>,
+
+	/// The unprotected header of the signature, if any.
+	pub unprotected_header : Option<JsonObject>,
+
+	/// The signature.
+	pub signature          : Vec<u8>,
+}
+
+/// A message verified with [`decode_verify_threshold`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThresholdVerifiedMessage {
+	/// The decoded message with only the matched signatures.
+	pub message : DecodedJsonMessage,
+
+	/// The verifiers that matched a signature, in the order of the verifiers.
+	pub matches : Vec<KeyMatch>,
+}
+
+/// A verifier of a [`ThresholdVerifier`] that accepted a signature.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeyMatch {
+	/// The index of the verifier in the [`ThresholdVerifier`].
+	pub verifier  : usize,
+
+	/// The index of the signature in the message.
+	pub signature : usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct RawMessage {
+	payload    : String,
+	signatures : Vec<RawSignature>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct RawSignature {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	protected : Option<String>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	header    : Option<JsonObject>,
+
+	signature : String,
+}
+
+/// Encode and sign a message using the general JWS JSON Serialization with one signature per signer.
+///
+/// Each signer adds its header parameters to its own copy of `header`, which becomes the protected header of its signature.
+/// The message is returned as JSON text.
+///
+/// With the `std` feature, each signature is reported to the global [`crate::observer::Observer`], if any.
+pub fn encode_sign(header: JsonObject, payload: &[u8], signers: &impl MultiSigner) -> Result<String> {
+	let payload = base64::encode_config(payload, base64::URL_SAFE_NO_PAD);
+
+	let mut signatures = Vec::new();
+	for signer in signers.signers() {
+		#[cfg(feature = "std")]
+		let observation = Observation::start(Operation::Sign);
+		let mut header = header.clone();
+		signer.set_header_params(&mut header);
+
+		// Serializing header can't fail since it's already a JSON object.
+		let protected = base64::encode_config(serde_json::to_vec(&header).unwrap(), base64::URL_SAFE_NO_PAD);
+		let signature = signer.compute_mac(protected.as_bytes(), payload.as_bytes());
+		#[cfg(feature = "std")]
+		observation.finish(Some(&header), signature.as_ref().map(|_| ()));
+		let signature = signature?;
+		signatures.push(RawSignature {
+			protected : Some(protected),
+			header    : None,
+			signature : base64::encode_config(&signature, base64::URL_SAFE_NO_PAD),
+		});
+	}
+
+	if signatures.is_empty() {
+		return Err(Error::other("no signers to sign the message with"));
+	}
+
+	serde_json::to_string(&RawMessage{payload, signatures}).map_err(Error::from)
+}
+
+/// Decode a message in the general JWS JSON Serialization without verifying the signatures.
+///
+/// You can use [`decode_verify`] to decode the message and verify the signatures.
+pub fn decode_unverified(data: &[u8]) -> Result<DecodedJsonMessage> {
+	let raw = parse_message(data)?;
+	let payload = decode_base64_url(raw.payload.as_bytes(), MessagePart::Payload)?;
+	let signatures = raw.signatures.iter().enumerate()
+		.map(|(index, signature)| decode_signature(index, signature))
+		.collect::<Result<Vec<_>>>()?;
+	Ok(DecodedJsonMessage{payload, signatures})
+}
+
+/// Decode a message in the general JWS JSON Serialization and verify that at least one signature is valid.
+///
+/// Every signature is checked independently with the verifier, using the protected and unprotected header of that signature.
+/// The returned message contains only the signatures that were verified successfully.
+/// If no signature is valid, the error of the verifier is returned.
+///
+/// To require signatures from a minimum number of distinct keys, use [`decode_verify_threshold`] instead.
+///
+/// With the `std` feature, the operation is reported to the global [`crate::observer::Observer`], if any.
+pub fn decode_verify(data: &[u8], verifier: &impl Verifier) -> Result<DecodedJsonMessage> {
+	#[cfg(feature = "std")]
+	let observation = Observation::start(Operation::Verify);
+	let result = verify_signatures(data, verifier);
+	#[cfg(feature = "std")]
+	observation.finish(result.as_ref().ok().and_then(|message| observed_header(&message.signatures)), result.as_ref().map(|_| ()));
+	result
+}
+
+/// Decode a message and verify all signatures, keeping the valid ones.
+fn verify_signatures(data: &[u8], verifier: &impl Verifier) -> Result<DecodedJsonMessage> {
+	let raw = parse_message(data)?;
+	let payload = decode_base64_url(raw.payload.as_bytes(), MessagePart::Payload)?;
+
+	let mut verified = Vec::new();
+	let mut error    = None;
+	for (index, raw_signature) in raw.signatures.iter().enumerate() {
+		let signature = decode_signature(index, raw_signature)?;
+		let encoded_header = raw_signature.protected.as_deref().unwrap_or("");
+		let result = verifier.verify(
+			signature.protected_header.as_ref(),
+			signature.unprotected_header.as_ref(),
+			encoded_header.as_bytes(),
+			raw.payload.as_bytes(),
+			&signature.signature,
+		);
+		match result {
+			Ok(())  => verified.push(signature),
+			Err(e)  => error = Some(match error {
+				None           => e,
+				Some(previous) => crate::combine::select_error(previous, e),
+			}),
+		}
+	}
+
+	if verified.is_empty() {
+		Err(error.unwrap_or_else(|| Error::invalid_message("message has no signatures")))
+	} else {
+		Ok(DecodedJsonMessage{payload, signatures: verified})
+	}
+}
+
+/// Decode a message in the general JWS JSON Serialization and verify that enough keys of a [`ThresholdVerifier`] signed it.
+///
+/// This is the entry point for k-of-n verification: the threshold counts distinct keys, not signatures.
+/// Each verifier of the threshold verifier is checked against the signatures of the message.
+/// Verifiers are matched to the signatures they accept such that as many verifiers as possible match a distinct signature,
+/// so each key and each signature counts only once towards the threshold.
+///
+/// The result reports which verifiers matched which signatures.
+/// If too few verifiers matched, an [`Error::InvalidSignature`] error is returned,
+/// unless no verifier matched at all, in which case the most relevant error of the verifiers is returned.
+///
+/// With the `std` feature, the operation is reported to the global [`crate::observer::Observer`], if any.
+pub fn decode_verify_threshold<V: Verifier>(data: &[u8], verifier: &ThresholdVerifier<V>) -> Result<ThresholdVerifiedMessage> {
+	#[cfg(feature = "std")]
+	let observation = Observation::start(Operation::Verify);
+	let result = match_keys(data, verifier);
+	#[cfg(feature = "std")]
+	observation.finish(result.as_ref().ok().and_then(|verified| observed_header(&verified.message.signatures)), result.as_ref().map(|_| ()));
+	result
+}
+
+/// Decode a message and match the verifiers of a threshold verifier against its signatures.
+fn match_keys<V: Verifier>(data: &[u8], verifier: &ThresholdVerifier<V>) -> Result<ThresholdVerifiedMessage> {
+	let raw = parse_message(data)?;
+	let payload = decode_base64_url(raw.payload.as_bytes(), MessagePart::Payload)?;
+	let signatures = raw.signatures.iter().enumerate()
+		.map(|(index, signature)| decode_signature(index, signature))
+		.collect::<Result<Vec<_>>>()?;
+
+	// Find out which verifiers accept which signatures.
+	let mut accepted = Vec::with_capacity(verifier.verifiers().len());
+	let mut error    = None;
+	for key in verifier.verifiers() {
+		let mut accepted_by_key = Vec::new();
+		for (signature, raw_signature) in signatures.iter().zip(&raw.signatures) {
+			let result = key.verify(
+				signature.protected_header.as_ref(),
+				signature.unprotected_header.as_ref(),
+				raw_signature.protected.as_deref().unwrap_or("").as_bytes(),
+				raw.payload.as_bytes(),
+				&signature.signature,
+			);
+			match result {
+				Ok(()) => accepted_by_key.push(signature.index),
+				Err(e) => error = Some(match error {
+					None           => e,
+					Some(previous) => crate::combine::select_error(previous, e),
+				}),
+			}
+		}
+		accepted.push(accepted_by_key);
+	}
+
+	let owners = maximum_matching(&accepted, signatures.len());
+	let mut matches : Vec<_> = owners.iter().enumerate()
+		.filter_map(|(signature, owner)| owner.map(|verifier| KeyMatch{verifier, signature}))
+		.collect();
+	matches.sort_by_key(|key_match| key_match.verifier);
+
+	if matches.len() < verifier.threshold() {
+		return Err(verifier.threshold_error(matches.len(), error));
+	}
+
+	let signatures = signatures.into_iter().filter(|signature| owners[signature.index].is_some()).collect();
+	Ok(ThresholdVerifiedMessage{message: DecodedJsonMessage{payload, signatures}, matches})
+}
+
+/// Match verifiers to the signatures they accept, using each verifier and each signature at most once.
+///
+/// This finds a maximum bipartite matching with augmenting paths,
+/// so a verifier that accepts multiple signatures does not take the only signature another verifier accepts.
+///
+/// Returns the index of the matched verifier for each signature.
+fn maximum_matching(accepted: &[Vec<usize>], signatures: usize) -> Vec<Option<usize>> {
+	let mut owners = alloc::vec![None; signatures];
+	for verifier in 0..accepted.len() {
+		let mut visited = alloc::vec![false; signatures];
+		augment(verifier, accepted, &mut owners, &mut visited);
+	}
+	owners
+}
+
+/// Try to match a verifier to a signature, moving other verifiers to other signatures if needed.
+fn augment(verifier: usize, accepted: &[Vec<usize>], owners: &mut [Option<usize>], visited: &mut [bool]) -> bool {
+	for &signature in &accepted[verifier] {
+		if visited[signature] {
+			continue;
+		}
+		visited[signature] = true;
+		if owners[signature].is_none_or(|owner| augment(owner, accepted, owners, visited)) {
+			owners[signature] = Some(verifier);
+			return true;
+		}
+	}
+	false
+}
+
+/// Get the header of the first verified signature to report to the observer.
+#[cfg(feature = "std")]
+fn observed_header(signatures: &[DecodedSignature]) -> Option<&JsonObject> {
+	let signature = signatures.first()?;
+	signature.protected_header.as_ref().or(signature.unprotected_header.as_ref())
+}
+
+/// Parse the JSON structure of a message.
+fn parse_message(data: &[u8]) -> Result<RawMessage> {
+	serde_json::from_slice(data).map_err(|e| Error::invalid_message(format!("invalid JWS JSON Serialization: {}", e)).maybe_with_source(e))
+}
+
+/// Decode the headers and signature of a single signature.
+fn decode_signature(index: usize, raw: &RawSignature) -> Result<DecodedSignature> {
+	let protected_header : Option<JsonObject> = match &raw.protected {
+		None            => None,
+		Some(protected) => Some(decode_json(&decode_base64_url(protected.as_bytes(), MessagePart::Header)?, MessagePart::Header)?),
+	};
+	let unprotected_header = raw.header.clone();
+
+	// The protected and unprotected header parameters must be disjoint.
+	if let (Some(protected), Some(unprotected)) = (&protected_header, &unprotected_header) {
+		if let Some(name) = unprotected.keys().find(|name| protected.contains_key(*name)) {
+			return Err(Error::invalid_message(format!("header parameter {} appears in both the protected and unprotected header", name)).with_header_param(name.as_str()));
+		}
+	}
+
+	let signature = decode_base64_url(raw.signature.as_bytes(), MessagePart::Signature)?;
+	Ok(DecodedSignature{index, protected_header, unprotected_header, signature})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use crate::hmac::{HmacVerifier, Hs256Signer, Hs512Signer};
+	use crate::none::NoneVerifier;
+	use crate::Signer;
+
+	use assert2::assert;
+
+	const KEY_A : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+	const KEY_B : &[u8] = b"this is another secret key that is long enough for HMAC-SHA-512!";
+
+	#[test]
+	fn test_encode_decode_multiple_signatures() {
+		let signers = Hs256Signer::new(KEY_A).unwrap().and(Hs512Signer::new(KEY_B).unwrap());
+		let encoded = encode_sign(json_object!{"typ": "release-manifest"}, b"foo", &signers).unwrap();
+
+		let message = decode_unverified(encoded.as_bytes()).unwrap();
+		assert!(message.payload == b"foo");
+		assert!(message.signatures.len() == 2);
+		assert!(message.signatures[0].protected_header == Some(json_object!{"alg": "HS256", "typ": "release-manifest"}));
+		assert!(message.signatures[1].protected_header == Some(json_object!{"alg": "HS512", "typ": "release-manifest"}));
+
+		// Each client can verify the signature it knows the key for.
+		let verifier_a = HmacVerifier::new(KEY_A).unwrap();
+		let verifier_b = HmacVerifier::new(KEY_B).unwrap();
+		let message = decode_verify(encoded.as_bytes(), &verifier_a).unwrap();
+		assert!(message.signatures.len() == 1);
+		assert!(message.signatures[0].index == 0);
+		assert!(let Ok(_) = decode_verify(encoded.as_bytes(), &verifier_b));
+
+		// All valid signatures are returned.
+		let message = decode_verify(encoded.as_bytes(), &verifier_a.or(verifier_b)).unwrap();
+		assert!(message.signatures.len() == 2);
+
+		// Without any valid signature, the error of the verifier is reported.
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = decode_verify(encoded.as_bytes(), &NoneVerifier));
+	}
+
+	#[test]
+	fn test_decode_tampered() {
+		let signers = Hs256Signer::new(KEY_A).unwrap().and(Hs256Signer::new(KEY_B).unwrap()).and(Hs512Signer::new(KEY_A).unwrap());
+		let encoded = encode_sign(json_object!{}, b"foo", &signers).unwrap();
+		let verifier = HmacVerifier::new(KEY_A).unwrap().or(HmacVerifier::new(KEY_B).unwrap());
+		assert!(decode_verify(encoded.as_bytes(), &verifier).unwrap().signatures.len() == 3);
+
+		// Replace the last signature with the first one.
+		let mut raw : RawMessage = serde_json::from_str(&encoded).unwrap();
+		raw.signatures[2].signature = raw.signatures[0].signature.clone();
+		let tampered = serde_json::to_string(&raw).unwrap();
+		let message = decode_verify(tampered.as_bytes(), &verifier).unwrap();
+		assert!(message.signatures.iter().map(|x| x.index).collect::<Vec<_>>() == [0, 1]);
+
+		// Header parameters may not be both protected and unprotected.
+		raw.signatures[0].header = Some(json_object!{"alg": "none"});
+		let tampered = serde_json::to_string(&raw).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_verify(tampered.as_bytes(), &verifier));
+	}
+
+	#[test]
+	fn test_decode_verify_threshold() {
+		const KEY_C : &[u8] = b"this is a third secret key, also long enough for HMAC-SHA-512!!!";
+		let signers = Hs256Signer::new(KEY_C).unwrap().and(Hs512Signer::new(KEY_A).unwrap());
+		let encoded = encode_sign(json_object!{}, b"foo", &signers).unwrap();
+
+		// Two of three maintainers must have signed the message.
+		let maintainers = vec![HmacVerifier::new(KEY_A).unwrap(), HmacVerifier::new(KEY_B).unwrap(), HmacVerifier::new(KEY_C).unwrap()];
+		let verified = decode_verify_threshold(encoded.as_bytes(), &ThresholdVerifier::new(2, maintainers.clone()).unwrap()).unwrap();
+		assert!(verified.matches == [KeyMatch{verifier: 0, signature: 1}, KeyMatch{verifier: 2, signature: 0}]);
+		assert!(verified.message.payload == b"foo");
+		assert!(verified.message.signatures.iter().map(|x| x.index).collect::<Vec<_>>() == [0, 1]);
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = decode_verify_threshold(encoded.as_bytes(), &ThresholdVerifier::new(3, maintainers.clone()).unwrap()));
+
+		// The same key counts only once, even if it signed the message twice.
+		let signers = Hs256Signer::new(KEY_A).unwrap().and(Hs512Signer::new(KEY_A).unwrap());
+		let encoded = encode_sign(json_object!{}, b"foo", &signers).unwrap();
+		let verified = decode_verify_threshold(encoded.as_bytes(), &ThresholdVerifier::new(1, maintainers.clone()).unwrap()).unwrap();
+		assert!(verified.matches == [KeyMatch{verifier: 0, signature: 0}]);
+		assert!(verified.message.signatures.len() == 1);
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = decode_verify_threshold(encoded.as_bytes(), &ThresholdVerifier::new(2, maintainers).unwrap()));
+
+		// Without any matching key, the error of the verifiers is reported.
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = decode_verify_threshold(encoded.as_bytes(), &ThresholdVerifier::new(1, vec![NoneVerifier]).unwrap()));
+	}
+
+	#[test]
+	fn test_decode_verify_threshold_overlapping() {
+		const KEY_C : &[u8] = b"this is a third secret key, also long enough for HMAC-SHA-512!!!";
+		let signers = Hs256Signer::new(KEY_B).unwrap().and(Hs256Signer::new(KEY_A).unwrap());
+		let encoded = encode_sign(json_object!{}, b"foo", &signers).unwrap();
+
+		// The first verifier accepts both signatures, the second only the first signature.
+		// Matching the first verifier with the first signature would leave the second verifier without a signature.
+		let verifiers = vec![
+			HmacVerifier::new(KEY_A).unwrap().or(HmacVerifier::new(KEY_B).unwrap()),
+			HmacVerifier::new(KEY_B).unwrap().or(HmacVerifier::new(KEY_C).unwrap()),
+		];
+		let verified = decode_verify_threshold(encoded.as_bytes(), &ThresholdVerifier::new(2, verifiers).unwrap()).unwrap();
+		assert!(verified.matches == [KeyMatch{verifier: 0, signature: 1}, KeyMatch{verifier: 1, signature: 0}]);
+		assert!(verified.message.signatures.len() == 2);
+	}
+
+	#[test]
+	fn test_threshold_verifier_new() {
+		let verifiers = || vec![HmacVerifier::new(KEY_A).unwrap().with_label("key-a"), HmacVerifier::new(KEY_B).unwrap().with_label("key-b")];
+		assert!(let Ok(_) = ThresholdVerifier::new(2, verifiers()));
+		assert!(let Err(Error { kind: Error::Other, .. }) = ThresholdVerifier::new(0, verifiers()));
+		assert!(let Err(Error { kind: Error::Other, .. }) = ThresholdVerifier::new(3, verifiers()));
+
+		// The same key can not be counted twice.
+		let duplicate = vec![HmacVerifier::new(KEY_A).unwrap().with_label("key-a"), HmacVerifier::new(KEY_B).unwrap().with_label("key-a")];
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = ThresholdVerifier::new(1, duplicate));
+	}
+
+	#[test]
+	fn test_decode_invalid() {
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_verify(b"not json", &NoneVerifier));
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_verify(br#"{"payload": "Zm9v", "signatures": []}"#, &NoneVerifier));
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_verify(br#"{"payload": "Zm9v*", "signatures": []}"#, &NoneVerifier));
+
+		// A signature without protected header is verified with the unprotected header only.
+		assert!(let Ok(_) = decode_verify(br#"{"payload": "Zm9v", "signatures": [{"header": {"alg": "none"}, "signature": ""}]}"#, &NoneVerifier));
+	}
+}
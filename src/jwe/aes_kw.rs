@@ -0,0 +1,120 @@
+//! [`Encrypter`] and [`Decrypter`] implementations for the `A128KW` and `A256KW` algorithms.
+//!
+//! These algorithms are defined in [RFC 7518 section 4.4](https://tools.ietf.org/html/rfc7518#section-4.4).
+//! They wrap a random content encryption key with a shared symmetric key using AES Key Wrap from [RFC 3394](https://tools.ietf.org/html/rfc3394).
+
+use aes_kw::{KekAes128, KekAes256};
+use zeroize::Zeroizing;
+
+use crate::{Error, JsonObject, JsonValue, KeyMaterial, parse_required_header_param, Result};
+use crate::key::Redacted;
+use super::{random_bytes, ContentKey, Decrypter, Encrypter};
+
+/// Message encrypter and decrypter for the AES Key Wrap algorithms.
+///
+/// The algorithm is determined by the length of the key:
+/// a 16 byte key is used for `A128KW` and a 32 byte key is used for `A256KW`.
+///
+/// The wrapped key type may be anything that implements [`KeyMaterial`].
+/// You can use a `Vec<u8>` to have the encrypter own the key,
+/// or a `&[u8]` to prevent copying the key more than necessary.
+#[derive(Clone)]
+pub struct AesKeyWrap<Key: KeyMaterial> {
+	key: Key,
+}
+
+impl<K: KeyMaterial> AesKeyWrap<K> {
+	/// Create a new AES Key Wrap encrypter and decrypter using a specified key.
+	pub fn new(key: K) -> Self {
+		Self{key}
+	}
+
+	/// Get the name of the algorithm used with the key.
+	fn algorithm(&self) -> Result<&'static str> {
+		match self.key.as_ref().len() {
+			16 => Ok("A128KW"),
+			32 => Ok("A256KW"),
+			n  => Err(Error::invalid_key(format!("AES key wrap requires a 16 or 32 byte key, got {} bytes", n))),
+		}
+	}
+}
+
+impl<K: KeyMaterial> Drop for AesKeyWrap<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> std::fmt::Debug for AesKeyWrap<K> {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("AesKeyWrap").field("key", &Redacted).finish()
+	}
+}
+
+impl<K: KeyMaterial> Encrypter for AesKeyWrap<K> {
+	fn encrypt_key(&self, header: &mut JsonObject, key_length: usize) -> Result<ContentKey> {
+		let algorithm = self.algorithm()?;
+		let key = random_bytes(key_length);
+		let key_encryption_key = self.key.as_ref();
+		let encrypted_key = match algorithm {
+			"A128KW" => KekAes128::new(key_encryption_key.into()).wrap_vec(&key),
+			_        => KekAes256::new(key_encryption_key.into()).wrap_vec(&key),
+		};
+		let encrypted_key = encrypted_key.map_err(|e| Error::other(format!("failed to wrap content encryption key: {}", e)))?;
+
+		header.insert("alg".to_string(), JsonValue::from(algorithm));
+		Ok(ContentKey{key, encrypted_key})
+	}
+}
+
+impl<K: KeyMaterial> Decrypter for AesKeyWrap<K> {
+	fn decrypt_key(&self, header: &JsonObject, encrypted_key: &[u8], key_length: usize) -> Result<Vec<u8>> {
+		let algorithm : &str = parse_required_header_param(Some(header), None, "alg")?;
+		if algorithm != self.algorithm()? {
+			return Err(Error::unsupported_encryption_algorithm(algorithm.to_string()).with_header_param("alg").with_algorithm(algorithm));
+		}
+
+		let key_encryption_key = self.key.as_ref();
+		let key = match algorithm {
+			"A128KW" => KekAes128::new(key_encryption_key.into()).unwrap_vec(encrypted_key),
+			_        => KekAes256::new(key_encryption_key.into()).unwrap_vec(encrypted_key),
+		};
+		let mut key = Zeroizing::new(key.map_err(|_| Error::decryption_failed("failed to unwrap content encryption key"))?);
+
+		if key.len() != key_length {
+			return Err(Error::decryption_failed("unwrapped content encryption key has the wrong length"));
+		}
+		Ok(std::mem::take(&mut *key))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use assert2::assert;
+
+	#[test]
+	fn test_wrap_unwrap() {
+		for &(key_length, algorithm) in &[(16, "A128KW"), (32, "A256KW")] {
+			let key = AesKeyWrap::new(vec![5; key_length]);
+			let mut header = json_object!{};
+			let content_key = key.encrypt_key(&mut header, 32).unwrap();
+			assert!(header == json_object!{"alg": algorithm});
+			assert!(content_key.key.len() == 32);
+			assert!(content_key.encrypted_key.len() == 40);
+			assert!(key.decrypt_key(&header, &content_key.encrypted_key, 32).unwrap() == content_key.key);
+
+			// Unwrapping with a different key must fail.
+			let other_key = AesKeyWrap::new(vec![6; key_length]);
+			assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = other_key.decrypt_key(&header, &content_key.encrypted_key, 32));
+			assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = key.decrypt_key(&header, &content_key.encrypted_key, 16));
+		}
+	}
+
+	#[test]
+	fn test_invalid_key_length() {
+		let key = AesKeyWrap::new(b"too short");
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = key.encrypt_key(&mut json_object!{}, 16));
+	}
+}
@@ -0,0 +1,232 @@
+//! JWE Compact Serialization implementaton.
+//!
+//! This module contains types and functions to encode/decode
+//! and encrypt/decrypt messages encoded with the JWE Compact Serialization Scheme
+//! as defined in [RFC 7516 section 7.1](https://tools.ietf.org/html/rfc7516#section-7.1).
+//!
+//! Decoded messages are represented by the same [`DecodedMessage`] type as JWS messages.
+
+use zeroize::Zeroizing;
+
+use crate::compact::{base64_len, decode_base64_url, decode_json, DecodedMessage};
+use crate::{get_header_param, Error, JsonObject, JsonValue, MessagePart, parse_required_header_param, Result};
+use super::{random_bytes, ContentEncryption, Decrypter, Encrypter};
+
+/// Encode and encrypt a message using the JWE Compact Serialization scheme.
+///
+/// This function will first use the [`Encrypter`] to determine the content encryption key and add header parameters to the header.
+/// The `enc` header parameter is set according to the content encryption algorithm.
+/// The protected header is then encoded and used as additional authenticated data when encrypting the payload.
+pub fn encode_encrypt(header: JsonObject, payload: &[u8], encryption: ContentEncryption, encrypter: &impl Encrypter) -> Result<EncodedEncryptedMessage> {
+	let mut header = header;
+	header.insert("enc".to_string(), JsonValue::from(encryption.name()));
+
+	// Let the encrypter set the headers before encoding the message.
+	let key = encrypter.encrypt_key(&mut header, encryption.key_length())?;
+
+	// Serializing header can't fail since it's already a JSON object.
+	let header_json = serde_json::to_vec(&header).unwrap();
+	let encoded_header = base64::encode_config(&header_json, base64::URL_SAFE_NO_PAD);
+
+	// Encrypt the payload with the encoded header as additional authenticated data.
+	let iv = random_bytes(encryption.iv_length());
+	let (ciphertext, tag) = encryption.encrypt(&key.key, &iv, encoded_header.as_bytes(), payload)?;
+
+	let parts = [&key.encrypted_key[..], &iv, &ciphertext, &tag];
+	let mut data = encoded_header;
+	data.reserve(parts.iter().map(|x| base64_len(x.len()) + 1).sum());
+	for part in &parts {
+		data.push('.');
+		base64::encode_config_buf(part, base64::URL_SAFE_NO_PAD, &mut data);
+	}
+
+	Ok(EncodedEncryptedMessage{data})
+}
+
+/// Decode and decrypt a JWE Compact Serialization message.
+///
+/// The [`Decrypter`] is used to recover the content encryption key.
+/// The content encryption algorithm is determined by the `enc` header parameter.
+pub fn decode_decrypt(data: &[u8], decrypter: &impl Decrypter) -> Result<DecodedMessage> {
+	split_encoded_parts(data)?.decrypt(decrypter)
+}
+
+/// An encoded and encrypted JWE Compact Serialization message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncodedEncryptedMessage {
+	data: String,
+}
+
+impl EncodedEncryptedMessage {
+	/// Get a reference to the raw data.
+	pub fn data(&self) -> &str {
+		&self.data
+	}
+
+	/// Get the raw data, consuming the encoded message.
+	pub fn into_data(self) -> String {
+		self.data
+	}
+
+	/// Get a reference to the raw data as bytes.
+	pub fn as_bytes(&self) -> &[u8] {
+		self.data().as_bytes()
+	}
+
+	/// Get the parts of the message as a [`CompactSerializedParts`] struct.
+	pub fn parts(&self) -> CompactSerializedParts<'_> {
+		// The message was created by us, so it always has the right number of parts.
+		split_encoded_parts(self.as_bytes()).unwrap()
+	}
+}
+
+/// The individual (still encoded) parts of a JWE Compact Serialized message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompactSerializedParts<'a> {
+	pub header:        &'a [u8],
+	pub encrypted_key: &'a [u8],
+	pub iv:            &'a [u8],
+	pub ciphertext:    &'a [u8],
+	pub tag:           &'a [u8],
+}
+
+impl<'a> CompactSerializedParts<'a> {
+	/// Decode the protected header of the message.
+	pub fn decode_header(&self) -> Result<JsonObject> {
+		let header = decode_base64_url(self.header, MessagePart::Header)?;
+		decode_json(&header, MessagePart::Header)
+	}
+
+	/// Decode and decrypt the already-split parts of a JWE Compact Serialization message.
+	pub fn decrypt(&self, decrypter: &impl Decrypter) -> Result<DecodedMessage> {
+		let header = self.decode_header()?;
+
+		let encryption : &str = parse_required_header_param(Some(&header), None, "enc")?;
+		let encryption = ContentEncryption::from_name(encryption)
+			.ok_or_else(|| Error::unsupported_encryption_algorithm(encryption.to_string()).with_header_param("enc").with_algorithm(encryption))?;
+
+		if get_header_param(Some(&header), None, "zip").is_some() {
+			return Err(Error::unsupported_encryption_algorithm("compressed payloads are not supported").with_header_param("zip"));
+		}
+
+		let encrypted_key = decode_base64_url(self.encrypted_key, MessagePart::EncryptedKey)?;
+		let iv            = decode_base64_url(self.iv,            MessagePart::InitializationVector)?;
+		let ciphertext    = decode_base64_url(self.ciphertext,    MessagePart::Ciphertext)?;
+		let tag           = decode_base64_url(self.tag,           MessagePart::AuthenticationTag)?;
+
+		let key = Zeroizing::new(decrypter.decrypt_key(&header, &encrypted_key, encryption.key_length())?);
+		if iv.len() != encryption.iv_length() {
+			return Err(Error::invalid_message("initialization vector has the wrong length"));
+		}
+		let payload = encryption.decrypt(&key, &iv, self.header, &ciphertext, &tag)?;
+
+		Ok(DecodedMessage{header, payload})
+	}
+}
+
+/// Split the parts of a JWE Compact Serialization message.
+///
+/// A JWE Compact Serialization message contains five base64-url encoded parts separated by period '.' characters:
+///   - header
+///   - encrypted key
+///   - initialization vector
+///   - ciphertext
+///   - authentication tag
+///
+/// This function splits a byte slice into these five parts.
+pub fn split_encoded_parts(data: &[u8]) -> Result<CompactSerializedParts<'_>> {
+	// Split data into parts.
+	let mut parts = data.splitn(6, |&c| c == b'.');
+
+	let header        = parts.next().ok_or_else(|| Error::invalid_message("encoded message does not contain a header"))?;
+	let encrypted_key = parts.next().ok_or_else(|| Error::invalid_message("encoded message does not contain an encrypted key"))?;
+	let iv            = parts.next().ok_or_else(|| Error::invalid_message("encoded message does not contain an initialization vector"))?;
+	let ciphertext    = parts.next().ok_or_else(|| Error::invalid_message("encoded message does not contain a ciphertext"))?;
+	let tag           = parts.next().ok_or_else(|| Error::invalid_message("encoded message does not contain an authentication tag"))?;
+
+	// Make sure there are no additional message parts in the input.
+	if parts.next().is_some() {
+		return Err(Error::invalid_message("encoded message contains an additional field after the authentication tag"));
+	}
+
+	Ok(CompactSerializedParts{header, encrypted_key, iv, ciphertext, tag})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use crate::jwe::aes_kw::AesKeyWrap;
+	use crate::jwe::dir::DirectKey;
+
+	use assert2::assert;
+
+	// Example taken from RFC 7516 appendix A.3
+	// https://tools.ietf.org/html/rfc7516#appendix-A.3
+	//
+	// Header:
+	//   {"alg":"A128KW","enc":"A128CBC-HS256"}
+	//
+	// Plaintext:
+	//   Live long and prosper.
+	//
+	// Key: GawgguFyGrWKav7AX4VKUg
+
+	const RFC7516_A3_ENCODED : &[u8] = b"eyJhbGciOiJBMTI4S1ciLCJlbmMiOiJBMTI4Q0JDLUhTMjU2In0.6KB707dM9YTIgHtLvtgWQ8mKwboJW3of9locizkDTHzBC2IlrT1oOQ.AxY8DCtDaGlsbGljb3RoZQ.KDlTtXchhZTGufMYmOYGS4HffxPSUrfmqCHXaI9wOGY.U0m_YmjN04DJvceFICbCVQ";
+	const RFC7516_A3_KEY     : &[u8] = &[25, 172, 32, 130, 225, 114, 26, 181, 138, 106, 254, 192, 95, 133, 74, 82];
+
+	#[test]
+	fn test_split_encoded_parts() {
+		let parts = split_encoded_parts(b"aap.noot.mies.wim.zus").unwrap();
+		assert!(parts.header == b"aap");
+		assert!(parts.encrypted_key == b"noot");
+		assert!(parts.iv == b"mies");
+		assert!(parts.ciphertext == b"wim");
+		assert!(parts.tag == b"zus");
+
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = split_encoded_parts(b"aap.noot.mies"));
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = split_encoded_parts(b"aap.noot.mies.wim"));
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = split_encoded_parts(b"aap.noot.mies.wim.zus."));
+	}
+
+	#[test]
+	fn test_decode_decrypt_rfc7516_a3() {
+		let message = decode_decrypt(RFC7516_A3_ENCODED, &AesKeyWrap::new(RFC7516_A3_KEY)).unwrap();
+		assert!(message.header == json_object!{"alg": "A128KW", "enc": "A128CBC-HS256"});
+		assert!(message.payload == b"Live long and prosper.");
+
+		// Decrypting with the wrong key must fail.
+		assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = decode_decrypt(RFC7516_A3_ENCODED, &AesKeyWrap::new(&[0; 16])));
+		assert!(let Err(Error { kind: Error::UnsupportedEncryptionAlgorithm, .. }) = decode_decrypt(RFC7516_A3_ENCODED, &AesKeyWrap::new(&[0; 32])));
+	}
+
+	#[test]
+	fn test_encode_decrypt() {
+		let header = json_object!{"typ": "JWT"};
+		for &encryption in &[ContentEncryption::A128CbcHs256, ContentEncryption::A128Gcm, ContentEncryption::A256Gcm] {
+			let key = DirectKey::new(vec![9; encryption.key_length()]);
+			let encrypted = encode_encrypt(header.clone(), b"foo", encryption, &key).unwrap();
+			let decrypted = decode_decrypt(encrypted.as_bytes(), &key).unwrap();
+			assert!(decrypted.payload == b"foo");
+			assert!(decrypted.header == json_object!{"typ": "JWT", "alg": "dir", "enc": encryption.name()});
+			assert!(encrypted.parts().encrypted_key == b"");
+		}
+	}
+
+	#[test]
+	fn test_decrypt_tampered() {
+		let key = AesKeyWrap::new(&[1; 32]);
+		let encrypted = encode_encrypt(json_object!{}, b"foo", ContentEncryption::A256Gcm, &key).unwrap();
+		let parts = encrypted.parts();
+
+		// Changing the protected header invalidates the authentication tag.
+		let header = base64::encode_config(br#"{"alg":"A256KW","enc":"A256GCM","x":1}"#, base64::URL_SAFE_NO_PAD);
+		let tampered = CompactSerializedParts{header: header.as_bytes(), ..parts};
+		assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = tampered.decrypt(&key));
+
+		// Compressed payloads are not supported.
+		let header = base64::encode_config(br#"{"alg":"A256KW","enc":"A256GCM","zip":"DEF"}"#, base64::URL_SAFE_NO_PAD);
+		let tampered = CompactSerializedParts{header: header.as_bytes(), ..parts};
+		assert!(let Err(Error { kind: Error::UnsupportedEncryptionAlgorithm, .. }) = tampered.decrypt(&key));
+	}
+}
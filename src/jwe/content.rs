@@ -0,0 +1,166 @@
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use cbc::cipher::block_padding::Pkcs7;
+use crypto_mac::{Mac, NewMac};
+use subtle::ConstantTimeEq;
+
+use crate::{Error, Result};
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+/// Content encryption algorithms as defined in [RFC 7518 section 5](https://tools.ietf.org/html/rfc7518#section-5).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ContentEncryption {
+	/// AES-128-CBC with HMAC-SHA-256 as authentication tag.
+	A128CbcHs256,
+
+	/// AES-GCM with a 128 bit key.
+	A128Gcm,
+
+	/// AES-GCM with a 256 bit key.
+	A256Gcm,
+}
+
+impl ContentEncryption {
+	/// Get the name of the algorithm as used for the `enc` header parameter.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::A128CbcHs256 => "A128CBC-HS256",
+			Self::A128Gcm      => "A128GCM",
+			Self::A256Gcm      => "A256GCM",
+		}
+	}
+
+	/// Get the algorithm from the value of an `enc` header parameter.
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"A128CBC-HS256" => Some(Self::A128CbcHs256),
+			"A128GCM"       => Some(Self::A128Gcm),
+			"A256GCM"       => Some(Self::A256Gcm),
+			_               => None,
+		}
+	}
+
+	/// Get the length of the content encryption key in bytes.
+	pub fn key_length(self) -> usize {
+		match self {
+			Self::A128CbcHs256 => 32,
+			Self::A128Gcm      => 16,
+			Self::A256Gcm      => 32,
+		}
+	}
+
+	/// Get the length of the initialization vector in bytes.
+	pub fn iv_length(self) -> usize {
+		match self {
+			Self::A128CbcHs256 => 16,
+			Self::A128Gcm      => 12,
+			Self::A256Gcm      => 12,
+		}
+	}
+
+	/// Encrypt a plaintext, returning the ciphertext and the authentication tag.
+	pub(crate) fn encrypt(self, key: &[u8], iv: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+		self.check_lengths(key, iv)?;
+		match self {
+			Self::A128CbcHs256 => {
+				let (mac_key, enc_key) = key.split_at(16);
+				let ciphertext = cbc::Encryptor::<aes::Aes128>::new(enc_key.into(), iv.into()).encrypt_padded_vec_mut::<Pkcs7>(plaintext);
+				let tag = cbc_hmac_tag(mac_key, aad, iv, &ciphertext)?;
+				Ok((ciphertext, tag))
+			},
+			Self::A128Gcm => gcm_encrypt(Aes128Gcm::new(key.into()), iv, aad, plaintext),
+			Self::A256Gcm => gcm_encrypt(Aes256Gcm::new(key.into()), iv, aad, plaintext),
+		}
+	}
+
+	/// Verify the authentication tag and decrypt a ciphertext.
+	pub(crate) fn decrypt(self, key: &[u8], iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+		self.check_lengths(key, iv)?;
+		match self {
+			Self::A128CbcHs256 => {
+				let (mac_key, enc_key) = key.split_at(16);
+				let expected_tag = cbc_hmac_tag(mac_key, aad, iv, ciphertext)?;
+				if !bool::from(expected_tag.ct_eq(tag)) {
+					return Err(Error::decryption_failed("invalid authentication tag"));
+				}
+				cbc::Decryptor::<aes::Aes128>::new(enc_key.into(), iv.into())
+					.decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+					.map_err(|_| Error::decryption_failed("invalid padding"))
+			},
+			Self::A128Gcm => gcm_decrypt(Aes128Gcm::new(key.into()), iv, aad, ciphertext, tag),
+			Self::A256Gcm => gcm_decrypt(Aes256Gcm::new(key.into()), iv, aad, ciphertext, tag),
+		}
+	}
+
+	/// Check that the key and initialization vector have the right length for the algorithm.
+	fn check_lengths(self, key: &[u8], iv: &[u8]) -> Result<()> {
+		if key.len() != self.key_length() {
+			Err(Error::invalid_key(format!("{} requires a {} byte content encryption key, got {} bytes", self.name(), self.key_length(), key.len())))
+		} else if iv.len() != self.iv_length() {
+			Err(Error::invalid_message(format!("{} requires a {} byte initialization vector, got {} bytes", self.name(), self.iv_length(), iv.len())))
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// Compute the authentication tag for AES-CBC-HMAC-SHA2 as described in RFC 7518 section 5.2.2.1.
+fn cbc_hmac_tag(mac_key: &[u8], aad: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+	let aad_bits = (aad.len() as u64 * 8).to_be_bytes();
+	let mut mac = HmacSha256::new_varkey(mac_key).map_err(|_| Error::invalid_key("invalid MAC key for A128CBC-HS256"))?;
+	mac.update(aad);
+	mac.update(iv);
+	mac.update(ciphertext);
+	mac.update(&aad_bits);
+	Ok(mac.finalize().into_bytes()[..16].to_vec())
+}
+
+fn gcm_encrypt(cipher: impl AeadInPlace, iv: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+	let mut buffer = plaintext.to_vec();
+	let tag = cipher.encrypt_in_place_detached(iv.into(), aad, &mut buffer)
+		.map_err(|_| Error::other("AES-GCM encryption failed"))?;
+	Ok((buffer, tag.to_vec()))
+}
+
+fn gcm_decrypt(cipher: impl AeadInPlace, iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+	if tag.len() != 16 {
+		return Err(Error::decryption_failed("invalid authentication tag"));
+	}
+	let mut buffer = ciphertext.to_vec();
+	cipher.decrypt_in_place_detached(iv.into(), aad, &mut buffer, tag.into())
+		.map_err(|_| Error::decryption_failed("invalid authentication tag"))?;
+	Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::assert;
+
+	#[test]
+	fn test_round_trip() {
+		for &algorithm in &[ContentEncryption::A128CbcHs256, ContentEncryption::A128Gcm, ContentEncryption::A256Gcm] {
+			let key = vec![7; algorithm.key_length()];
+			let iv  = vec![3; algorithm.iv_length()];
+			let (ciphertext, tag) = algorithm.encrypt(&key, &iv, b"aad", b"plaintext").unwrap();
+			assert!(ciphertext != b"plaintext");
+			assert!(algorithm.decrypt(&key, &iv, b"aad", &ciphertext, &tag).unwrap() == b"plaintext");
+
+			// Changing the additional authenticated data or tag must be detected.
+			let mut bad_tag = tag.clone();
+			bad_tag[0] ^= 1;
+			assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = algorithm.decrypt(&key, &iv, b"aab", &ciphertext, &tag));
+			assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = algorithm.decrypt(&key, &iv, b"aad", &ciphertext, &bad_tag));
+		}
+	}
+
+	#[test]
+	fn test_names() {
+		for &algorithm in &[ContentEncryption::A128CbcHs256, ContentEncryption::A128Gcm, ContentEncryption::A256Gcm] {
+			assert!(ContentEncryption::from_name(algorithm.name()) == Some(algorithm));
+		}
+		assert!(ContentEncryption::from_name("A192GCM") == None);
+	}
+}
@@ -0,0 +1,89 @@
+//! [`Encrypter`] and [`Decrypter`] implementations for the `dir` algorithm.
+//!
+//! The `dir` algorithm is defined in [RFC 7518 section 4.5](https://tools.ietf.org/html/rfc7518#section-4.5).
+//! It uses a shared symmetric key directly as content encryption key.
+
+use crate::{Error, JsonObject, JsonValue, KeyMaterial, parse_required_header_param, Result};
+use crate::key::Redacted;
+use super::{ContentKey, Decrypter, Encrypter};
+
+/// Message encrypter and decrypter for the `dir` algorithm.
+///
+/// The wrapped key type may be anything that implements [`KeyMaterial`].
+/// The key must have the exact length required by the content encryption algorithm.
+#[derive(Clone)]
+pub struct DirectKey<Key: KeyMaterial> {
+	key: Key,
+}
+
+impl<K: KeyMaterial> DirectKey<K> {
+	/// Create a new `dir` encrypter and decrypter using a specified key.
+	pub fn new(key: K) -> Self {
+		Self{key}
+	}
+
+	fn check_key_length(&self, key_length: usize) -> Result<()> {
+		if self.key.as_ref().len() == key_length {
+			Ok(())
+		} else {
+			Err(Error::unsupported_encryption_algorithm(format!("content encryption requires a {} byte key, but the direct key is {} bytes", key_length, self.key.as_ref().len())))
+		}
+	}
+}
+
+impl<K: KeyMaterial> Drop for DirectKey<K> {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+impl<K: KeyMaterial> std::fmt::Debug for DirectKey<K> {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("DirectKey").field("key", &Redacted).finish()
+	}
+}
+
+impl<K: KeyMaterial> Encrypter for DirectKey<K> {
+	fn encrypt_key(&self, header: &mut JsonObject, key_length: usize) -> Result<ContentKey> {
+		self.check_key_length(key_length)?;
+		header.insert("alg".to_string(), JsonValue::from("dir"));
+		Ok(ContentKey{key: self.key.as_ref().to_vec(), encrypted_key: Vec::new()})
+	}
+}
+
+impl<K: KeyMaterial> Decrypter for DirectKey<K> {
+	fn decrypt_key(&self, header: &JsonObject, encrypted_key: &[u8], key_length: usize) -> Result<Vec<u8>> {
+		let algorithm : &str = parse_required_header_param(Some(header), None, "alg")?;
+		if algorithm != "dir" {
+			return Err(Error::unsupported_encryption_algorithm(algorithm.to_string()).with_header_param("alg").with_algorithm(algorithm));
+		}
+		if !encrypted_key.is_empty() {
+			return Err(Error::invalid_message("encrypted key must be empty for the dir algorithm"));
+		}
+		self.check_key_length(key_length)?;
+		Ok(self.key.as_ref().to_vec())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use assert2::assert;
+
+	#[test]
+	fn test_direct_key() {
+		let key = DirectKey::new(b"0123456789abcdef");
+		let mut header = json_object!{};
+		let content_key = key.encrypt_key(&mut header, 16).unwrap();
+		assert!(header == json_object!{"alg": "dir"});
+		assert!(content_key.key == b"0123456789abcdef");
+		assert!(content_key.encrypted_key == b"");
+
+		assert!(key.decrypt_key(&header, b"", 16).unwrap() == b"0123456789abcdef");
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = key.decrypt_key(&header, b"foo", 16));
+		assert!(let Err(Error { kind: Error::UnsupportedEncryptionAlgorithm, .. }) = key.decrypt_key(&header, b"", 32));
+		assert!(let Err(Error { kind: Error::UnsupportedEncryptionAlgorithm, .. }) = key.decrypt_key(&json_object!{"alg": "A128KW"}, b"", 16));
+		assert!(let Err(Error { kind: Error::UnsupportedEncryptionAlgorithm, .. }) = key.encrypt_key(&mut header, 32));
+	}
+}
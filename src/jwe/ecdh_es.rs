@@ -0,0 +1,390 @@
+//! [`Encrypter`] and [`Decrypter`] implementations for the `ECDH-ES` family of algorithms.
+//!
+//! These algorithms are defined in [RFC 7518 section 4.6](https://tools.ietf.org/html/rfc7518#section-4.6).
+//! The sender generates an ephemeral key pair and performs Elliptic Curve Diffie-Hellman key agreement with the public key of the recipient.
+//! The agreed upon secret is fed to the Concat KDF to derive a key.
+//! For `ECDH-ES`, the derived key is used directly as content encryption key.
+//! For `ECDH-ES+A128KW` and `ECDH-ES+A256KW`, the derived key is used to wrap a random content encryption key with AES Key Wrap.
+//!
+//! The ephemeral public key is sent to the recipient in the `epk` header parameter.
+//! The optional `apu` and `apv` header parameters carry information about the producer and the recipient that is included in the key derivation.
+//!
+//! Only the P-256 curve is supported.
+//! The key types of the [`p256`](https://docs.rs/p256) crate are re-exported for convenience.
+
+use aes_kw::{KekAes128, KekAes256};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use sha2::Digest;
+use zeroize::Zeroizing;
+
+use crate::{Error, get_header_param, JsonObject, JsonValue, parse_required_header_param, Result};
+use crate::key::Redacted;
+use super::{random_bytes, ContentKey, Decrypter, Encrypter};
+
+pub use p256::{PublicKey, SecretKey};
+
+/// The key agreement algorithms of the `ECDH-ES` family.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EcdhEsAlgorithm {
+	/// Use the derived key directly as content encryption key.
+	EcdhEs,
+
+	/// Wrap the content encryption key with `A128KW` using the derived key.
+	EcdhEsA128Kw,
+
+	/// Wrap the content encryption key with `A256KW` using the derived key.
+	EcdhEsA256Kw,
+}
+
+/// Message encrypter for the `ECDH-ES` family of algorithms.
+#[derive(Clone, Debug)]
+pub struct EcdhEsEncrypter {
+	algorithm : EcdhEsAlgorithm,
+	recipient : PublicKey,
+	apu       : Option<Vec<u8>>,
+	apv       : Option<Vec<u8>>,
+}
+
+/// Message decrypter for the `ECDH-ES` family of algorithms.
+///
+/// The decrypter supports all algorithms in the family.
+#[derive(Clone)]
+pub struct EcdhEsDecrypter {
+	key: SecretKey,
+}
+
+impl std::fmt::Debug for EcdhEsDecrypter {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("EcdhEsDecrypter").field("key", &Redacted).finish()
+	}
+}
+
+impl EcdhEsAlgorithm {
+	/// Get the name of the algorithm as used for the `alg` header parameter.
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::EcdhEs       => "ECDH-ES",
+			Self::EcdhEsA128Kw => "ECDH-ES+A128KW",
+			Self::EcdhEsA256Kw => "ECDH-ES+A256KW",
+		}
+	}
+
+	/// Get the algorithm from the value of an `alg` header parameter.
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"ECDH-ES"        => Some(Self::EcdhEs),
+			"ECDH-ES+A128KW" => Some(Self::EcdhEsA128Kw),
+			"ECDH-ES+A256KW" => Some(Self::EcdhEsA256Kw),
+			_                => None,
+		}
+	}
+}
+
+impl EcdhEsEncrypter {
+	/// Create a new encrypter using the public key of the recipient.
+	pub fn new(algorithm: EcdhEsAlgorithm, recipient: PublicKey) -> Self {
+		Self{algorithm, recipient, apu: None, apv: None}
+	}
+
+	/// Set the agreement PartyUInfo, sent in the `apu` header parameter.
+	pub fn with_apu(mut self, apu: impl Into<Vec<u8>>) -> Self {
+		self.apu = Some(apu.into());
+		self
+	}
+
+	/// Set the agreement PartyVInfo, sent in the `apv` header parameter.
+	pub fn with_apv(mut self, apv: impl Into<Vec<u8>>) -> Self {
+		self.apv = Some(apv.into());
+		self
+	}
+
+	/// Encrypt the content encryption key using a specific ephemeral key.
+	fn encrypt_key_with_ephemeral_key(&self, header: &mut JsonObject, key_length: usize, ephemeral_key: &SecretKey) -> Result<ContentKey> {
+		let apu = self.apu.as_deref().unwrap_or(&[]);
+		let apv = self.apv.as_deref().unwrap_or(&[]);
+		let shared_secret = p256::ecdh::diffie_hellman(ephemeral_key.to_nonzero_scalar(), self.recipient.as_affine());
+		let mut derived_key = Zeroizing::new(derive_key(shared_secret.raw_secret_bytes(), self.algorithm, header_enc(header)?, apu, apv, key_length));
+
+		let (key, encrypted_key) = match self.algorithm {
+			EcdhEsAlgorithm::EcdhEs => (std::mem::take(&mut *derived_key), Vec::new()),
+			EcdhEsAlgorithm::EcdhEsA128Kw | EcdhEsAlgorithm::EcdhEsA256Kw => {
+				let key = random_bytes(key_length);
+				let encrypted_key = wrap_key(self.algorithm, &derived_key, &key)?;
+				(key, encrypted_key)
+			},
+		};
+
+		header.insert("alg".to_string(), JsonValue::from(self.algorithm.name()));
+		header.insert("epk".to_string(), encode_public_key(&ephemeral_key.public_key()));
+		if let Some(apu) = &self.apu {
+			header.insert("apu".to_string(), JsonValue::from(base64::encode_config(apu, base64::URL_SAFE_NO_PAD)));
+		}
+		if let Some(apv) = &self.apv {
+			header.insert("apv".to_string(), JsonValue::from(base64::encode_config(apv, base64::URL_SAFE_NO_PAD)));
+		}
+		Ok(ContentKey{key, encrypted_key})
+	}
+}
+
+impl EcdhEsDecrypter {
+	/// Create a new decrypter using the private key of the recipient.
+	pub fn new(key: SecretKey) -> Self {
+		Self{key}
+	}
+}
+
+impl Encrypter for EcdhEsEncrypter {
+	fn encrypt_key(&self, header: &mut JsonObject, key_length: usize) -> Result<ContentKey> {
+		let ephemeral_key = SecretKey::random(&mut rand_core::OsRng);
+		self.encrypt_key_with_ephemeral_key(header, key_length, &ephemeral_key)
+	}
+}
+
+impl Decrypter for EcdhEsDecrypter {
+	fn decrypt_key(&self, header: &JsonObject, encrypted_key: &[u8], key_length: usize) -> Result<Vec<u8>> {
+		let algorithm : &str = parse_required_header_param(Some(header), None, "alg")?;
+		let algorithm = EcdhEsAlgorithm::from_name(algorithm)
+			.ok_or_else(|| Error::unsupported_encryption_algorithm(algorithm.to_string()).with_header_param("alg").with_algorithm(algorithm))?;
+
+		let ephemeral_key = decode_public_key(get_header_param(Some(header), None, "epk").ok_or_else(|| Error::missing_header_param("epk").with_header_param("epk"))?)?;
+		let apu = decode_party_info(header, "apu")?;
+		let apv = decode_party_info(header, "apv")?;
+
+		let shared_secret = p256::ecdh::diffie_hellman(self.key.to_nonzero_scalar(), ephemeral_key.as_affine());
+		let mut derived_key = Zeroizing::new(derive_key(shared_secret.raw_secret_bytes(), algorithm, header_enc(header)?, &apu, &apv, key_length));
+
+		match algorithm {
+			EcdhEsAlgorithm::EcdhEs => {
+				if !encrypted_key.is_empty() {
+					return Err(Error::invalid_message("encrypted key must be empty for the ECDH-ES algorithm"));
+				}
+				Ok(std::mem::take(&mut *derived_key))
+			},
+			EcdhEsAlgorithm::EcdhEsA128Kw | EcdhEsAlgorithm::EcdhEsA256Kw => {
+				let mut key = unwrap_key(algorithm, &derived_key, encrypted_key)?;
+				if key.len() != key_length {
+					return Err(Error::decryption_failed("unwrapped content encryption key has the wrong length"));
+				}
+				Ok(std::mem::take(&mut *key))
+			},
+		}
+	}
+}
+
+/// Get the `enc` header parameter, needed as algorithm ID for the direct key agreement mode.
+fn header_enc(header: &JsonObject) -> Result<&str> {
+	parse_required_header_param(Some(header), None, "enc")
+}
+
+/// Derive a key from the shared secret using the Concat KDF.
+///
+/// See [RFC 7518 section 4.6.2](https://tools.ietf.org/html/rfc7518#section-4.6.2).
+fn derive_key(shared_secret: &[u8], algorithm: EcdhEsAlgorithm, enc: &str, apu: &[u8], apv: &[u8], key_length: usize) -> Vec<u8> {
+	// In direct key agreement mode, the derived key is the content encryption key.
+	// Otherwise, it is the key used to wrap the content encryption key.
+	let (algorithm_id, key_length) = match algorithm {
+		EcdhEsAlgorithm::EcdhEs       => (enc, key_length),
+		EcdhEsAlgorithm::EcdhEsA128Kw => (algorithm.name(), 16),
+		EcdhEsAlgorithm::EcdhEsA256Kw => (algorithm.name(), 32),
+	};
+
+	let mut other_info = Vec::new();
+	for field in &[algorithm_id.as_bytes(), apu, apv] {
+		other_info.extend_from_slice(&(field.len() as u32).to_be_bytes());
+		other_info.extend_from_slice(field);
+	}
+	other_info.extend_from_slice(&(key_length as u32 * 8).to_be_bytes());
+
+	let mut output = Vec::with_capacity(key_length);
+	let mut counter = 1u32;
+	while output.len() < key_length {
+		let mut digest = sha2::Sha256::new();
+		digest.update(counter.to_be_bytes());
+		digest.update(shared_secret);
+		digest.update(&other_info);
+		output.extend_from_slice(&digest.finalize());
+		counter += 1;
+	}
+	output.truncate(key_length);
+	output
+}
+
+fn wrap_key(algorithm: EcdhEsAlgorithm, key_encryption_key: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+	let encrypted_key = match algorithm {
+		EcdhEsAlgorithm::EcdhEsA128Kw => KekAes128::new(key_encryption_key.into()).wrap_vec(key),
+		_                             => KekAes256::new(key_encryption_key.into()).wrap_vec(key),
+	};
+	encrypted_key.map_err(|e| Error::other(format!("failed to wrap content encryption key: {}", e)))
+}
+
+fn unwrap_key(algorithm: EcdhEsAlgorithm, key_encryption_key: &[u8], encrypted_key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+	let key = match algorithm {
+		EcdhEsAlgorithm::EcdhEsA128Kw => KekAes128::new(key_encryption_key.into()).unwrap_vec(encrypted_key),
+		_                             => KekAes256::new(key_encryption_key.into()).unwrap_vec(encrypted_key),
+	};
+	key.map(Zeroizing::new).map_err(|_| Error::decryption_failed("failed to unwrap content encryption key"))
+}
+
+/// Encode a public key as JWK for the `epk` header parameter.
+fn encode_public_key(key: &PublicKey) -> JsonValue {
+	let point = key.to_encoded_point(false);
+	// Uncompressed points always have both coordinates.
+	let x = base64::encode_config(point.x().unwrap(), base64::URL_SAFE_NO_PAD);
+	let y = base64::encode_config(point.y().unwrap(), base64::URL_SAFE_NO_PAD);
+	serde_json::json!({"kty": "EC", "crv": "P-256", "x": x, "y": y})
+}
+
+/// Decode a public key from the JWK in the `epk` header parameter.
+fn decode_public_key(value: &JsonValue) -> Result<PublicKey> {
+	let invalid = || Error::invalid_header_param("epk").with_header_param("epk");
+	let key = value.as_object().ok_or_else(invalid)?;
+	let kty = key.get("kty").and_then(|x| x.as_str()).ok_or_else(invalid)?;
+	let crv = key.get("crv").and_then(|x| x.as_str()).ok_or_else(invalid)?;
+	if kty != "EC" || crv != "P-256" {
+		return Err(Error::unsupported_encryption_algorithm(format!("ephemeral key of type {} with curve {}", kty, crv)));
+	}
+
+	let x = key.get("x").and_then(|x| x.as_str()).ok_or_else(invalid)?;
+	let y = key.get("y").and_then(|x| x.as_str()).ok_or_else(invalid)?;
+	let x = base64::decode_config(x, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+	let y = base64::decode_config(y, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+	if x.len() != 32 || y.len() != 32 {
+		return Err(invalid());
+	}
+
+	// Build an uncompressed SEC1 point, which also checks that the point is on the curve.
+	let mut point = Vec::with_capacity(65);
+	point.push(4);
+	point.extend_from_slice(&x);
+	point.extend_from_slice(&y);
+	PublicKey::from_sec1_bytes(&point).map_err(|_| invalid())
+}
+
+/// Decode the optional `apu` or `apv` header parameter.
+fn decode_party_info(header: &JsonObject, key: &str) -> Result<Vec<u8>> {
+	match get_header_param(Some(header), None, key) {
+		None => Ok(Vec::new()),
+		Some(JsonValue::String(x)) => base64::decode_config(x, base64::URL_SAFE_NO_PAD).map_err(|e| Error::invalid_header_param(key).with_header_param(key).with_source(e)),
+		Some(_) => Err(Error::invalid_header_param(key).with_header_param(key)),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use crate::jwe::ContentEncryption;
+	use crate::jwe::compact::{decode_decrypt, encode_encrypt};
+
+	use assert2::assert;
+
+	// Example taken from RFC 7518 appendix C
+	// https://tools.ietf.org/html/rfc7518#appendix-C
+	//
+	// Alice (the producer) uses an ephemeral key to agree on a key for A128GCM with Bob (the consumer).
+	//
+	// Alice: {"kty":"EC", "crv":"P-256",
+	//         "x":"gI0GAILBdu7T53akrFmMyGcsF3n5dO7MmwNBHKW5SV0",
+	//         "y":"SLW_xSffzlPWrHEVI30DHM_4egVwt3NQqeUD7nMFpps",
+	//         "d":"0_NxaRPUMQoAJt50Gz8YiTr8gRTwyEaCumd-MToTmIo"}
+	//
+	// Bob:   {"kty":"EC", "crv":"P-256",
+	//         "x":"weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ",
+	//         "y":"e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck",
+	//         "d":"VEmDZpDXXK8p8N0Cndsxs924q6nS1RXFASRl6BfUqdw"}
+	//
+	// Header:
+	//   {"alg":"ECDH-ES", "enc":"A128GCM", "apu":"QWxpY2U", "apv":"Qm9i", "epk": <Alice's public key>}
+	//
+	// Derived key: VqqN6vgjbSBcIijNcacQGg
+
+	const RFC7518_C_ALICE_D : &str = "0_NxaRPUMQoAJt50Gz8YiTr8gRTwyEaCumd-MToTmIo";
+	const RFC7518_C_ALICE_X : &str = "gI0GAILBdu7T53akrFmMyGcsF3n5dO7MmwNBHKW5SV0";
+	const RFC7518_C_ALICE_Y : &str = "SLW_xSffzlPWrHEVI30DHM_4egVwt3NQqeUD7nMFpps";
+	const RFC7518_C_BOB_D   : &str = "VEmDZpDXXK8p8N0Cndsxs924q6nS1RXFASRl6BfUqdw";
+	const RFC7518_C_BOB_X   : &str = "weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ";
+	const RFC7518_C_BOB_Y   : &str = "e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck";
+	const RFC7518_C_KEY     : &str = "VqqN6vgjbSBcIijNcacQGg";
+
+	fn secret_key(d: &str) -> SecretKey {
+		SecretKey::from_slice(&base64::decode_config(d, base64::URL_SAFE_NO_PAD).unwrap()).unwrap()
+	}
+
+	fn public_key(x: &str, y: &str) -> PublicKey {
+		decode_public_key(&serde_json::json!({"kty": "EC", "crv": "P-256", "x": x, "y": y})).unwrap()
+	}
+
+	#[test]
+	fn test_rfc7518_appendix_c_encrypt() {
+		let alice = secret_key(RFC7518_C_ALICE_D);
+		let bob   = public_key(RFC7518_C_BOB_X, RFC7518_C_BOB_Y);
+
+		let encrypter = EcdhEsEncrypter::new(EcdhEsAlgorithm::EcdhEs, bob).with_apu(&b"Alice"[..]).with_apv(&b"Bob"[..]);
+		let mut header = json_object!{"enc": "A128GCM"};
+		let key = encrypter.encrypt_key_with_ephemeral_key(&mut header, 16, &alice).unwrap();
+
+		assert!(base64::encode_config(&key.key, base64::URL_SAFE_NO_PAD) == RFC7518_C_KEY);
+		assert!(key.encrypted_key == b"");
+		assert!(header == json_object!{
+			"alg": "ECDH-ES",
+			"enc": "A128GCM",
+			"apu": "QWxpY2U",
+			"apv": "Qm9i",
+			"epk": serde_json::json!({"kty": "EC", "crv": "P-256", "x": RFC7518_C_ALICE_X, "y": RFC7518_C_ALICE_Y}),
+		});
+	}
+
+	#[test]
+	fn test_rfc7518_appendix_c_decrypt() {
+		let bob = EcdhEsDecrypter::new(secret_key(RFC7518_C_BOB_D));
+		let header = json_object!{
+			"alg": "ECDH-ES",
+			"enc": "A128GCM",
+			"apu": "QWxpY2U",
+			"apv": "Qm9i",
+			"epk": serde_json::json!({"kty": "EC", "crv": "P-256", "x": RFC7518_C_ALICE_X, "y": RFC7518_C_ALICE_Y}),
+		};
+
+		let key = bob.decrypt_key(&header, b"", 16).unwrap();
+		assert!(base64::encode_config(&key, base64::URL_SAFE_NO_PAD) == RFC7518_C_KEY);
+	}
+
+	#[test]
+	fn test_debug_redacts_key() {
+		let bob = EcdhEsDecrypter::new(secret_key(RFC7518_C_BOB_D));
+		assert!(format!("{:?}", bob) == "EcdhEsDecrypter { key: <redacted> }");
+	}
+
+	#[test]
+	fn test_encode_decrypt_ecdh_es() {
+		let bob = SecretKey::random(&mut rand_core::OsRng);
+		let decrypter = EcdhEsDecrypter::new(bob.clone());
+		let other = EcdhEsDecrypter::new(SecretKey::random(&mut rand_core::OsRng));
+
+		for &algorithm in &[EcdhEsAlgorithm::EcdhEs, EcdhEsAlgorithm::EcdhEsA128Kw, EcdhEsAlgorithm::EcdhEsA256Kw] {
+			let encrypter = EcdhEsEncrypter::new(algorithm, bob.public_key()).with_apv(&b"Bob"[..]);
+			let encrypted = encode_encrypt(json_object!{}, b"foo", ContentEncryption::A128CbcHs256, &encrypter).unwrap();
+			let decrypted = decode_decrypt(encrypted.as_bytes(), &decrypter).unwrap();
+			assert!(decrypted.payload == b"foo");
+			assert!(decrypted.header.get("alg").and_then(|x| x.as_str()) == Some(algorithm.name()));
+			assert!(decrypted.header.get("apv").and_then(|x| x.as_str()) == Some("Qm9i"));
+			assert!(let Some(_) = decrypted.header.get("epk"));
+
+			// Another recipient can not decrypt the message.
+			assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = decode_decrypt(encrypted.as_bytes(), &other));
+		}
+	}
+
+	#[test]
+	fn test_invalid_epk() {
+		let decrypter = EcdhEsDecrypter::new(SecretKey::random(&mut rand_core::OsRng));
+		let header = json_object!{"alg": "ECDH-ES", "enc": "A128GCM"};
+		assert!(let Err(Error { kind: Error::MissingHeaderParam, .. }) = decrypter.decrypt_key(&header, b"", 16));
+
+		let header = json_object!{"alg": "ECDH-ES", "enc": "A128GCM", "epk": serde_json::json!({"kty": "EC", "crv": "P-256", "x": RFC7518_C_ALICE_X, "y": RFC7518_C_BOB_Y})};
+		assert!(let Err(Error { kind: Error::InvalidHeaderParam, .. }) = decrypter.decrypt_key(&header, b"", 16));
+
+		let header = json_object!{"alg": "ECDH-ES", "enc": "A128GCM", "epk": serde_json::json!({"kty": "EC", "crv": "P-384", "x": "", "y": ""})};
+		assert!(let Err(Error { kind: Error::UnsupportedEncryptionAlgorithm, .. }) = decrypter.decrypt_key(&header, b"", 16));
+	}
+}
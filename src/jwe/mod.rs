@@ -0,0 +1,110 @@
+//! JSON Web Encryption as described in [RFC 7516](https://tools.ietf.org/html/rfc7516).
+//!
+//! Encryption of a JWE message happens in two steps.
+//! First, a content encryption key is determined and encrypted for the recipient using a key management algorithm.
+//! Then, the payload is encrypted with the content encryption key using a content encryption algorithm.
+//!
+//! Key management is done through the [`Encrypter`] and [`Decrypter`] traits.
+//! This module contains implementations of these traits for the following key management algorithms:
+//!   - `dir` in the [`dir`] module.
+//!   - `A128KW` and `A256KW` in the [`aes_kw`] module.
+//!   - `RSA-OAEP` in the [`rsa`] module.
+//!   - `ECDH-ES`, `ECDH-ES+A128KW` and `ECDH-ES+A256KW` in the [`ecdh_es`] module.
+//!
+//! The supported content encryption algorithms are listed in the [`ContentEncryption`] enum.
+//!
+//! Encoding and decoding is available for the JWE Compact Serialization scheme in the [`compact`] module.
+//! Signed-then-encrypted nested JWTs are supported by the [`nested`] module.
+//!
+//! # Example:
+//! ```
+//! use jws::JsonObject;
+//! use jws::jwe::ContentEncryption;
+//! use jws::jwe::aes_kw::AesKeyWrap;
+//! use jws::jwe::compact::{decode_decrypt, encode_encrypt};
+//!
+//! fn encrypt_decrypt() -> jws::Result<()> {
+//!   let key = AesKeyWrap::new(b"0123456789abcdef");
+//!
+//!   // Encrypt the message.
+//!   let encoded = encode_encrypt(JsonObject::new(), b"payload", ContentEncryption::A128Gcm, &key)?;
+//!
+//!   // Decrypt the message.
+//!   let decoded = decode_decrypt(encoded.as_bytes(), &key)?;
+//!
+//!   assert_eq!(decoded.payload, b"payload");
+//!   assert_eq!(decoded.header.get("enc").and_then(|x| x.as_str()), Some("A128GCM"));
+//!
+//!   Ok(())
+//! }
+//! # encrypt_decrypt().unwrap();
+//! ```
+
+use crate::{JsonObject, Result};
+
+pub mod aes_kw;
+pub mod compact;
+pub mod dir;
+pub mod ecdh_es;
+pub mod nested;
+pub mod rsa;
+mod content;
+
+pub use content::ContentEncryption;
+
+/// A content encryption key along with the encrypted form that is sent to the recipient.
+///
+/// The plain key is zeroized when the content key is dropped.
+#[derive(Clone)]
+pub struct ContentKey {
+	/// The plain content encryption key.
+	pub key: Vec<u8>,
+
+	/// The encrypted key that is included in the message.
+	///
+	/// For key management algorithms that do not transmit the key, this is empty.
+	pub encrypted_key: Vec<u8>,
+}
+
+impl Drop for ContentKey {
+	fn drop(&mut self) {
+		zeroize::Zeroize::zeroize(&mut self.key);
+	}
+}
+
+/// An encrypter for JWE messages.
+pub trait Encrypter {
+	/// Determine the content encryption key for a new message and encrypt it for the recipient.
+	///
+	/// This is the first step in the encryption process, since the protected header is used as additional authenticated data for the content encryption.
+	/// The encrypter must set the `alg` header parameter, and any other parameters the recipient needs to recover the content encryption key.
+	///
+	/// The returned content encryption key must be exactly `key_length` bytes long,
+	/// as required by the content encryption algorithm.
+	fn encrypt_key(&self, header: &mut JsonObject, key_length: usize) -> Result<ContentKey>;
+}
+
+/// A decrypter for JWE messages.
+pub trait Decrypter {
+	/// Recover the content encryption key of a JWE message.
+	///
+	/// This function needs access to the decoded message header in order to determine which key management algorithm to use.
+	///
+	/// If the key management algorithm is not supported by the decrypter, it should return a [`crate::Error::UnsupportedEncryptionAlgorithm`] error.
+	/// If the key can not be decrypted, it should return a [`crate::Error::DecryptionFailed`] error.
+	/// It may also report any of the other supported error variants.
+	///
+	/// # Args:
+	///   - header:        The parsed protected header.
+	///   - encrypted_key: The decoded encrypted key of the message.
+	///   - key_length:    The length of the content encryption key required by the content encryption algorithm.
+	fn decrypt_key(&self, header: &JsonObject, encrypted_key: &[u8], key_length: usize) -> Result<Vec<u8>>;
+}
+
+/// Generate a buffer of cryptographically secure random bytes.
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+	use rand_core::RngCore;
+	let mut buffer = vec![0; len];
+	rand_core::OsRng.fill_bytes(&mut buffer);
+	buffer
+}
@@ -0,0 +1,108 @@
+//! Nested JWTs that are signed and then encrypted.
+//!
+//! A nested JWT is a JWS Compact Serialization message that is used as payload of a JWE Compact Serialization message,
+//! as described in [RFC 7519 section 5.2](https://tools.ietf.org/html/rfc7519#section-5.2) and [section 7.1](https://tools.ietf.org/html/rfc7519#section-7.1).
+//! The `cty` header parameter of the encrypted message must be `JWT` to indicate that the payload is itself a JWT.
+//!
+//! Most applications should use [`encode_sign_encrypt`] and [`decode_decrypt_verify`].
+
+use crate::compact::{self, DecodedMessage};
+use crate::{Error, get_header_param, JsonObject, JsonValue, Result, Signer, Verifier};
+use super::compact::{decode_decrypt, encode_encrypt, EncodedEncryptedMessage};
+use super::{ContentEncryption, Decrypter, Encrypter};
+
+/// A decoded, decrypted and verified nested JWT.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedNestedMessage {
+	/// The header of the outer encrypted message.
+	pub encryption_header: JsonObject,
+
+	/// The inner signed message.
+	pub message: DecodedMessage,
+}
+
+/// Sign a message and then encrypt the signed message.
+///
+/// The message is first signed with [`crate::compact::encode_sign`] using `header` and `signer`.
+/// The result is then encrypted with [`encode_encrypt`] using `encryption_header`, `encryption` and `encrypter`.
+/// The `cty` parameter of the encryption header is set to `JWT`.
+pub fn encode_sign_encrypt(
+	header            : JsonObject,
+	encryption_header : JsonObject,
+	payload           : &[u8],
+	signer            : &impl Signer,
+	encryption        : ContentEncryption,
+	encrypter         : &impl Encrypter,
+) -> Result<EncodedEncryptedMessage> {
+	let signed = compact::encode_sign(header, payload, signer)?;
+
+	let mut encryption_header = encryption_header;
+	encryption_header.insert("cty".to_string(), JsonValue::from("JWT"));
+	encode_encrypt(encryption_header, signed.as_bytes(), encryption, encrypter)
+}
+
+/// Decrypt a message and then verify the signed message contained in it.
+///
+/// The outer message must have a `cty` header parameter with the value `JWT` (compared case insensitively),
+/// and the decrypted payload must be a JWS Compact Serialization message.
+/// Otherwise, an [`Error::InvalidMessage`] error is returned.
+pub fn decode_decrypt_verify(data: &[u8], decrypter: &impl Decrypter, verifier: &impl Verifier) -> Result<DecodedNestedMessage> {
+	let decrypted = decode_decrypt(data, decrypter)?;
+
+	match get_header_param(Some(&decrypted.header), None, "cty") {
+		Some(JsonValue::String(x)) if x.eq_ignore_ascii_case("JWT") => (),
+		Some(_) => return Err(Error::invalid_message("content type of encrypted message is not JWT")),
+		None    => return Err(Error::invalid_message("encrypted message does not contain a nested JWT")),
+	}
+
+	let message = compact::decode_verify(&decrypted.payload, verifier)?;
+	Ok(DecodedNestedMessage{encryption_header: decrypted.header, message})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+	use crate::jwe::dir::DirectKey;
+
+	use assert2::assert;
+
+	#[test]
+	fn test_nested_round_trip() {
+		let key    = DirectKey::new([4; 16]);
+		let signer = Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap();
+
+		let encrypted = encode_sign_encrypt(json_object!{"typ": "JWT"}, json_object!{"kid": "a"}, b"foo", &signer, ContentEncryption::A128Gcm, &key).unwrap();
+		let decoded   = decode_decrypt_verify(encrypted.as_bytes(), &key, &HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+
+		assert!(decoded.encryption_header == json_object!{"alg": "dir", "enc": "A128GCM", "cty": "JWT", "kid": "a"});
+		assert!(decoded.message.header == json_object!{"alg": "HS256", "typ": "JWT"});
+		assert!(decoded.message.payload == b"foo");
+
+		// The inner signature is verified.
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = decode_decrypt_verify(encrypted.as_bytes(), &key, &HmacVerifier::new(b"this is not the key, even though it is long enough for HMAC-SHA512").unwrap()));
+	}
+
+	#[test]
+	fn test_nested_requires_cty() {
+		let key    = DirectKey::new([4; 16]);
+		let signed = compact::encode_sign(json_object!{}, b"foo", &Hs256Signer::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		let verifier = HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap();
+
+		// An encrypted JWS without content type is not accepted as nested JWT.
+		let encrypted = encode_encrypt(json_object!{}, signed.as_bytes(), ContentEncryption::A128Gcm, &key).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_decrypt_verify(encrypted.as_bytes(), &key, &verifier));
+
+		let encrypted = encode_encrypt(json_object!{"cty": "text/plain"}, signed.as_bytes(), ContentEncryption::A128Gcm, &key).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_decrypt_verify(encrypted.as_bytes(), &key, &verifier));
+
+		// The content type is case insensitive.
+		let encrypted = encode_encrypt(json_object!{"cty": "jwt"}, signed.as_bytes(), ContentEncryption::A128Gcm, &key).unwrap();
+		assert!(let Ok(_) = decode_decrypt_verify(encrypted.as_bytes(), &key, &verifier));
+
+		// The payload must be a signed message.
+		let encrypted = encode_encrypt(json_object!{"cty": "JWT"}, b"foo", ContentEncryption::A128Gcm, &key).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidMessage, .. }) = decode_decrypt_verify(encrypted.as_bytes(), &key, &verifier));
+	}
+}
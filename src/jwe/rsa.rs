@@ -0,0 +1,101 @@
+//! [`Encrypter`] and [`Decrypter`] implementations for the `RSA-OAEP` algorithm.
+//!
+//! The `RSA-OAEP` algorithm is defined in [RFC 7518 section 4.3](https://tools.ietf.org/html/rfc7518#section-4.3).
+//! It encrypts a random content encryption key with the public key of the recipient using RSAES-OAEP with SHA-1 and MGF1 with SHA-1.
+//!
+//! The key types of the [`rsa`](https://docs.rs/rsa) crate are re-exported for convenience.
+
+use ::rsa::Oaep;
+use zeroize::Zeroizing;
+
+use crate::{Error, JsonObject, JsonValue, parse_required_header_param, Result};
+use crate::key::Redacted;
+use super::{random_bytes, ContentKey, Decrypter, Encrypter};
+
+pub use ::rsa::{RsaPrivateKey, RsaPublicKey};
+
+/// Message encrypter for the `RSA-OAEP` algorithm.
+#[derive(Clone, Debug)]
+pub struct RsaOaepEncrypter {
+	key: RsaPublicKey,
+}
+
+/// Message decrypter for the `RSA-OAEP` algorithm.
+#[derive(Clone)]
+pub struct RsaOaepDecrypter {
+	key: RsaPrivateKey,
+}
+
+impl RsaOaepEncrypter {
+	/// Create a new `RSA-OAEP` encrypter using the public key of the recipient.
+	pub fn new(key: RsaPublicKey) -> Self {
+		Self{key}
+	}
+}
+
+impl RsaOaepDecrypter {
+	/// Create a new `RSA-OAEP` decrypter using a private key.
+	pub fn new(key: RsaPrivateKey) -> Self {
+		Self{key}
+	}
+}
+
+impl std::fmt::Debug for RsaOaepDecrypter {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("RsaOaepDecrypter").field("key", &Redacted).finish()
+	}
+}
+
+impl Encrypter for RsaOaepEncrypter {
+	fn encrypt_key(&self, header: &mut JsonObject, key_length: usize) -> Result<ContentKey> {
+		let key = random_bytes(key_length);
+		let encrypted_key = self.key.encrypt(&mut rand_core::OsRng, Oaep::new::<sha1::Sha1>(), &key)
+			.map_err(|e| Error::other(format!("failed to encrypt content encryption key: {}", e)))?;
+
+		header.insert("alg".to_string(), JsonValue::from("RSA-OAEP"));
+		Ok(ContentKey{key, encrypted_key})
+	}
+}
+
+impl Decrypter for RsaOaepDecrypter {
+	fn decrypt_key(&self, header: &JsonObject, encrypted_key: &[u8], key_length: usize) -> Result<Vec<u8>> {
+		let algorithm : &str = parse_required_header_param(Some(header), None, "alg")?;
+		if algorithm != "RSA-OAEP" {
+			return Err(Error::unsupported_encryption_algorithm(algorithm.to_string()).with_header_param("alg").with_algorithm(algorithm));
+		}
+
+		// Do not report why decryption failed, to avoid becoming a padding oracle.
+		let mut key = Zeroizing::new(self.key.decrypt(Oaep::new::<sha1::Sha1>(), encrypted_key)
+			.map_err(|_| Error::decryption_failed("failed to decrypt content encryption key"))?);
+
+		if key.len() != key_length {
+			return Err(Error::decryption_failed("failed to decrypt content encryption key"));
+		}
+		Ok(std::mem::take(&mut *key))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use crate::jwe::ContentEncryption;
+	use crate::jwe::compact::{decode_decrypt, encode_encrypt};
+	use assert2::assert;
+
+	#[test]
+	fn test_encode_decrypt_rsa_oaep() {
+		let private_key = RsaPrivateKey::new(&mut rand_core::OsRng, 1024).unwrap();
+		let encrypter   = RsaOaepEncrypter::new(private_key.to_public_key());
+		let decrypter   = RsaOaepDecrypter::new(private_key);
+
+		let encrypted = encode_encrypt(json_object!{"cty": "text/plain"}, b"foo", ContentEncryption::A256Gcm, &encrypter).unwrap();
+		let decrypted = decode_decrypt(encrypted.as_bytes(), &decrypter).unwrap();
+		assert!(decrypted.payload == b"foo");
+		assert!(decrypted.header == json_object!{"alg": "RSA-OAEP", "enc": "A256GCM", "cty": "text/plain"});
+
+		// A different private key can not decrypt the message.
+		let other_key = RsaOaepDecrypter::new(RsaPrivateKey::new(&mut rand_core::OsRng, 1024).unwrap());
+		assert!(let Err(Error { kind: Error::DecryptionFailed, .. }) = decode_decrypt(encrypted.as_bytes(), &other_key));
+	}
+}
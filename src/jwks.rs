@@ -0,0 +1,671 @@
+//! Fetching and caching of remote JSON Web Key Sets.
+//!
+//! The [`RemoteJwks`] verifier fetches a JWK set from a URL and uses it to verify messages.
+//! The HTTP layer is abstracted by the [`HttpClient`] and [`AsyncHttpClient`] traits, so any HTTP library can be used.
+//! With a [`HttpClient`], [`RemoteJwks`] is a [`Verifier`] that blocks while fetching the key set.
+//! With an [`AsyncHttpClient`], it is an [`AsyncVerifier`] that never blocks the executor,
+//! not even while waiting for another task to fetch the key set.
+//!
+//! The fetched key set is cached for the duration indicated by the `max-age` directive of the `Cache-Control` response header.
+//! When a message refers to a key ID that is not in the cached key set, the key set is fetched again,
+//! but never more often than the configured minimum refresh interval.
+//! This allows keys to be rotated without overwhelming the JWKS endpoint with requests for unknown key IDs.
+//! If fetching fails, the previously fetched key set is used.
+//!
+//! The key set is fetched without holding any locks, and only one thread or task fetches it at a time.
+//! Others keep verifying messages with the cached key set in the meantime.
+//! The keys are prepared for verification once per fetch, not for every message.
+//!
+//! See the [`jwk`](crate::jwk) module for the supported key types and algorithms.
+
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::jwk::{JwkSet, JwkSetVerifier};
+use crate::{AsyncVerifier, Error, get_header_param, JsonObject, Result, Verifier};
+
+/// An HTTP client used to fetch remote key sets.
+pub trait HttpClient {
+	/// Perform a GET request for a URL.
+	///
+	/// Transport errors should be reported as [`Error::Other`].
+	/// Unsuccessful HTTP status codes should be reported in the returned response.
+	fn get(&self, url: &str) -> Result<HttpResponse>;
+}
+
+/// An HTTP client used to fetch remote key sets asynchronously.
+pub trait AsyncHttpClient {
+	/// Perform a GET request for a URL.
+	///
+	/// Errors should be reported the same way as for [`HttpClient::get`].
+	fn get_async(&self, url: &str) -> impl Future<Output = Result<HttpResponse>> + Send;
+}
+
+/// A response to an HTTP request made by a [`HttpClient`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HttpResponse {
+	/// The HTTP status code.
+	pub status: u16,
+
+	/// The value of the `Cache-Control` header, if present.
+	pub cache_control: Option<String>,
+
+	/// The response body.
+	pub body: Vec<u8>,
+}
+
+/// A verifier that uses a JWK set fetched from a remote URL.
+///
+/// See the [module documentation](index.html) for details on caching and refreshing.
+pub struct RemoteJwks<Client> {
+	client               : Client,
+	url                  : String,
+	default_max_age      : Duration,
+	min_refresh_interval : Duration,
+	cache                : Mutex<Cache>,
+	fetched              : Condvar,
+}
+
+/// The cached key set along with the time it was fetched and when it expires.
+#[derive(Default)]
+struct Cache {
+	keys       : Option<Arc<Keys>>,
+	error      : Option<Error>,
+	fetched_at : Option<Instant>,
+	expires_at : Option<Instant>,
+	fetching   : bool,
+	waiters    : Vec<Waker>,
+}
+
+/// A fetched key set with the keys prepared for verification.
+struct Keys {
+	set      : Arc<JwkSet>,
+	verifier : JwkSetVerifier,
+}
+
+/// What to do next to get the current key set.
+enum Step {
+	/// Use the cached key set or error.
+	Use,
+	/// Wait for another thread or task to finish fetching the key set.
+	Wait,
+	/// Fetch the key set, the `fetching` flag has been set.
+	Fetch,
+}
+
+/// Marks the end of a fetch when dropped, even if the HTTP client panics or the fetching future is dropped.
+struct FetchGuard<'a>(&'a Mutex<Cache>, &'a Condvar);
+
+impl Drop for FetchGuard<'_> {
+	fn drop(&mut self) {
+		let mut cache = lock(self.0);
+		cache.fetching = false;
+		let waiters = std::mem::take(&mut cache.waiters);
+		drop(cache);
+		self.1.notify_all();
+		waiters.into_iter().for_each(Waker::wake);
+	}
+}
+
+fn lock(cache: &Mutex<Cache>) -> MutexGuard<'_, Cache> {
+	// The cache is always left in a consistent state, so a poisoned lock is fine.
+	cache.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl<Client> RemoteJwks<Client> {
+	/// Create a new remote JWK set for a URL.
+	///
+	/// The key set is not fetched until it is needed.
+	/// By default, the key set is cached for 5 minutes if the response has no `Cache-Control` header with a `max-age` directive,
+	/// and it is not refreshed more than once every 30 seconds.
+	pub fn new(client: Client, url: impl Into<String>) -> Self {
+		Self {
+			client,
+			url                  : url.into(),
+			default_max_age      : Duration::from_secs(300),
+			min_refresh_interval : Duration::from_secs(30),
+			cache                : Mutex::new(Cache::default()),
+			fetched              : Condvar::new(),
+		}
+	}
+
+	/// Set how long to cache the key set if the response does not specify a `max-age`.
+	pub fn with_default_max_age(mut self, max_age: Duration) -> Self {
+		self.default_max_age = max_age;
+		self
+	}
+
+	/// Set the minimum time between two fetches of the key set.
+	pub fn with_min_refresh_interval(mut self, interval: Duration) -> Self {
+		self.min_refresh_interval = interval;
+		self
+	}
+
+	/// Get the URL of the key set.
+	pub fn url(&self) -> &str {
+		&self.url
+	}
+
+	/// Get the HTTP client.
+	pub fn client(&self) -> &Client {
+		&self.client
+	}
+
+	/// Check if the key set may be fetched again according to the minimum refresh interval.
+	fn may_refresh(&self, cache: &Cache, now: Instant) -> bool {
+		match cache.fetched_at {
+			None             => true,
+			Some(fetched_at) => now.saturating_duration_since(fetched_at) >= self.min_refresh_interval,
+		}
+	}
+
+	/// Decide how to get the current key set for a key ID.
+	///
+	/// The key set is fetched if it has expired or if it does not contain the key ID.
+	/// While another thread or task is fetching the key set, the previous key set is used without waiting.
+	fn next_step(&self, cache: &mut Cache, now: Instant, kid: Option<&str>) -> Step {
+		if cache.fetching {
+			return if cache.keys.is_some() { Step::Use } else { Step::Wait };
+		}
+
+		let expired = cache.expires_at.map(|expires_at| now >= expires_at).unwrap_or(true);
+		let unknown_kid = match (&cache.keys, kid) {
+			(Some(keys), Some(kid)) => keys.verifier.find(kid).is_none(),
+			_ => false,
+		};
+
+		// Refresh the key set if the key ID is unknown, the keys may have been rotated.
+		if (expired || unknown_kid) && self.may_refresh(cache, now) {
+			cache.fetching   = true;
+			cache.fetched_at = Some(now);
+			Step::Fetch
+		} else {
+			Step::Use
+		}
+	}
+
+	/// Get the cached key set.
+	///
+	/// If fetching failed but a previous key set is available, the previous key set is used.
+	fn cached_keys(&self, cache: &Cache) -> Result<Arc<Keys>> {
+		match (&cache.keys, &cache.error) {
+			(Some(keys), _)     => Ok(keys.clone()),
+			(None, Some(error)) => Err(error.clone()),
+			(None, None)        => Err(Error::other(format!("no key set available from {}", self.url))),
+		}
+	}
+
+	/// Store the result of a fetch in the cache.
+	fn store(&self, now: Instant, result: Result<(Keys, Duration)>) -> Result<()> {
+		let mut cache = lock(&self.cache);
+		match result {
+			Ok((keys, max_age)) => {
+				cache.keys       = Some(Arc::new(keys));
+				cache.error      = None;
+				cache.expires_at = Some(now + max_age);
+				Ok(())
+			},
+			Err(e) => {
+				cache.error = Some(e.clone());
+				Err(e)
+			},
+		}
+	}
+
+	/// Parse and prepare a downloaded key set.
+	fn parse(&self, response: HttpResponse) -> Result<(Keys, Duration)> {
+		if response.status != 200 {
+			return Err(Error::other(format!("failed to fetch key set from {}: HTTP status {}", self.url, response.status)));
+		}
+
+		let set: JwkSet = serde_json::from_slice(&response.body)
+			.map_err(|e| Error::other(format!("failed to parse key set from {}: {}", self.url, e)))?;
+		let max_age = response.cache_control.as_deref().and_then(parse_max_age).unwrap_or(self.default_max_age);
+		Ok((Keys{verifier: set.verifier(), set: Arc::new(set)}, max_age))
+	}
+
+	/// Wait without blocking until no other task is fetching the key set, or until `step` says otherwise.
+	async fn poll_step(&self, mut step: impl FnMut(&mut Cache) -> Step) -> Step {
+		std::future::poll_fn(|context| {
+			let mut cache = lock(&self.cache);
+			match step(&mut cache) {
+				Step::Wait => {
+					if !cache.waiters.iter().any(|waiter| waiter.will_wake(context.waker())) {
+						cache.waiters.push(context.waker().clone());
+					}
+					Poll::Pending
+				},
+				step => Poll::Ready(step),
+			}
+		}).await
+	}
+}
+
+impl<Client: HttpClient> RemoteJwks<Client> {
+	/// Get the current key set, fetching it if the cached key set has expired.
+	pub fn keys(&self) -> Result<Arc<JwkSet>> {
+		Ok(self.current_keys(None)?.set.clone())
+	}
+
+	/// Fetch the key set now, regardless of the cached key set.
+	///
+	/// If another thread is fetching the key set, this waits for it to finish first.
+	pub fn refresh(&self) -> Result<()> {
+		let now = Instant::now();
+		let mut cache = lock(&self.cache);
+		while cache.fetching {
+			cache = self.wait(cache);
+		}
+		cache.fetching   = true;
+		cache.fetched_at = Some(now);
+		drop(cache);
+		self.fetch(now)
+	}
+
+	fn wait<'a>(&self, cache: MutexGuard<'a, Cache>) -> MutexGuard<'a, Cache> {
+		self.fetched.wait(cache).unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Get the cached key set, fetching it if it has expired or if it does not contain the key ID.
+	fn current_keys(&self, kid: Option<&str>) -> Result<Arc<Keys>> {
+		let now = Instant::now();
+		let mut cache = lock(&self.cache);
+		loop {
+			match self.next_step(&mut cache, now, kid) {
+				Step::Use   => break,
+				Step::Wait  => cache = self.wait(cache),
+				Step::Fetch => {
+					drop(cache);
+					// Errors are recorded in the cache and only reported if there is no key set.
+					let _ = self.fetch(now);
+					cache = lock(&self.cache);
+					break;
+				},
+			}
+		}
+		self.cached_keys(&cache)
+	}
+
+	/// Fetch the key set and store it in the cache.
+	///
+	/// The caller must have set the `fetching` flag, which is cleared again when the fetch is done.
+	fn fetch(&self, now: Instant) -> Result<()> {
+		let _guard = FetchGuard(&self.cache, &self.fetched);
+		let result = self.client.get(&self.url).and_then(|response| self.parse(response));
+		self.store(now, result)
+	}
+}
+
+impl<Client: AsyncHttpClient + Sync> RemoteJwks<Client> {
+	/// Get the current key set, fetching it asynchronously if the cached key set has expired.
+	pub async fn keys_async(&self) -> Result<Arc<JwkSet>> {
+		Ok(self.current_keys_async(None).await?.set.clone())
+	}
+
+	/// Fetch the key set asynchronously now, regardless of the cached key set.
+	///
+	/// If another thread or task is fetching the key set, this waits for it to finish first.
+	pub async fn refresh_async(&self) -> Result<()> {
+		let now = Instant::now();
+		self.poll_step(|cache| {
+			if cache.fetching {
+				return Step::Wait;
+			}
+			cache.fetching   = true;
+			cache.fetched_at = Some(now);
+			Step::Fetch
+		}).await;
+		self.fetch_async(now).await
+	}
+
+	/// Get the cached key set, fetching it asynchronously if it has expired or if it does not contain the key ID.
+	async fn current_keys_async(&self, kid: Option<&str>) -> Result<Arc<Keys>> {
+		let now = Instant::now();
+		if let Step::Fetch = self.poll_step(|cache| self.next_step(cache, now, kid)).await {
+			// Errors are recorded in the cache and only reported if there is no key set.
+			let _ = self.fetch_async(now).await;
+		}
+		self.cached_keys(&lock(&self.cache))
+	}
+
+	/// Fetch the key set asynchronously and store it in the cache.
+	///
+	/// The caller must have set the `fetching` flag, which is cleared again when the fetch is done or cancelled.
+	async fn fetch_async(&self, now: Instant) -> Result<()> {
+		let _guard = FetchGuard(&self.cache, &self.fetched);
+		let result = self.client.get_async(&self.url).await.and_then(|response| self.parse(response));
+		self.store(now, result)
+	}
+}
+
+impl<Client: HttpClient> Verifier for RemoteJwks<Client> {
+	fn verify(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
+		let kid = get_header_param(protected_header, unprotected_header, "kid").and_then(|x| x.as_str());
+		let keys = self.current_keys(kid)?;
+		keys.verifier.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature)
+	}
+}
+
+impl<Client: AsyncHttpClient + Sync> AsyncVerifier for RemoteJwks<Client> {
+	async fn verify_async(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> Result<()> {
+		let kid = get_header_param(protected_header, unprotected_header, "kid").and_then(|x| x.as_str());
+		let keys = self.current_keys_async(kid).await?;
+		keys.verifier.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature)
+	}
+}
+
+impl<Client: std::fmt::Debug> std::fmt::Debug for RemoteJwks<Client> {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("RemoteJwks")
+			.field("client", &self.client)
+			.field("url", &self.url)
+			.field("default_max_age", &self.default_max_age)
+			.field("min_refresh_interval", &self.min_refresh_interval)
+			.finish()
+	}
+}
+
+/// Parse the `max-age` directive from a `Cache-Control` header.
+///
+/// The `no-cache` and `no-store` directives result in a max age of zero.
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+	let mut max_age = None;
+	for directive in cache_control.split(',') {
+		let directive = directive.trim();
+		if directive.eq_ignore_ascii_case("no-cache") || directive.eq_ignore_ascii_case("no-store") {
+			return Some(Duration::from_secs(0));
+		}
+		let mut parts = directive.splitn(2, '=');
+		let name  = parts.next().unwrap_or("").trim();
+		let value = parts.next().map(|x| x.trim().trim_matches('"'));
+		if name.eq_ignore_ascii_case("max-age") {
+			max_age = value.and_then(|x| x.parse().ok()).map(Duration::from_secs);
+		}
+	}
+	max_age
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::compact;
+	use crate::hmac::Hs256Signer;
+	use crate::json_object;
+	use assert2::assert;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::mpsc;
+	use std::task::Context;
+
+	/// HTTP client that serves key sets from memory and counts the requests.
+	///
+	/// If a gate is set, the next request blocks until the gate is opened.
+	#[derive(Debug, Default)]
+	struct StandInServer {
+		response : Mutex<HttpResponse>,
+		requests : AtomicUsize,
+		gate     : Mutex<Option<mpsc::Receiver<()>>>,
+	}
+
+	impl StandInServer {
+		fn serve(&self, cache_control: Option<&str>, body: &str) {
+			*self.response.lock().unwrap() = HttpResponse {
+				status: 200,
+				cache_control: cache_control.map(String::from),
+				body: body.as_bytes().to_vec(),
+			};
+		}
+
+		fn requests(&self) -> usize {
+			self.requests.load(Ordering::SeqCst)
+		}
+	}
+
+	impl HttpClient for &StandInServer {
+		fn get(&self, url: &str) -> Result<HttpResponse> {
+			assert!(url == "https://example.com/jwks.json");
+			self.requests.fetch_add(1, Ordering::SeqCst);
+			let gate = self.gate.lock().unwrap().take();
+			if let Some(gate) = gate {
+				gate.recv().unwrap();
+			}
+			Ok(self.response.lock().unwrap().clone())
+		}
+	}
+
+	impl AsyncHttpClient for &StandInServer {
+		/// Yield to the executor once before responding, like a real HTTP request.
+		fn get_async(&self, url: &str) -> impl Future<Output = Result<HttpResponse>> + Send {
+			let response = HttpClient::get(self, url);
+			let mut pending = true;
+			std::future::poll_fn(move |context: &mut Context| {
+				if std::mem::take(&mut pending) {
+					context.waker().wake_by_ref();
+					Poll::Pending
+				} else {
+					Poll::Ready(response.clone())
+				}
+			})
+		}
+	}
+
+	const KEY_1 : &str = r#"{"keys": [{"kty": "oct", "kid": "key-1", "k": "dGhpcyBpcyBhIHNlY3JldCBrZXkgdGhhdCBpcyBsb25nIGVub3VnaCBmb3IgYWxsIEhNQUMgYWxnb3JpdGhtcw"}]}"#;
+	const KEY_2 : &str = r#"{"keys": [{"kty": "oct", "kid": "key-1", "k": "dGhpcyBpcyBhIHNlY3JldCBrZXkgdGhhdCBpcyBsb25nIGVub3VnaCBmb3IgYWxsIEhNQUMgYWxnb3JpdGhtcw"}, {"kty": "oct", "kid": "key-2", "k": "dGhpcyBpcyBhbm90aGVyIHNlY3JldCBrZXkgdGhhdCBpcyBsb25nIGVub3VnaCBmb3IgSE1BQy1TSEEtNTEyIQ"}]}"#;
+
+	fn sign(kid: &str, key: &[u8]) -> compact::EncodedSignedMessage {
+		compact::encode_sign(json_object!{"kid": kid}, b"foo", &Hs256Signer::new(key).unwrap()).unwrap()
+	}
+
+	#[test]
+	fn test_parse_max_age() {
+		assert!(parse_max_age("max-age=60") == Some(Duration::from_secs(60)));
+		assert!(parse_max_age("public, max-age=3600, must-revalidate") == Some(Duration::from_secs(3600)));
+		assert!(parse_max_age("public, no-cache") == Some(Duration::from_secs(0)));
+		assert!(parse_max_age("public") == None);
+		assert!(parse_max_age("max-age=foo") == None);
+	}
+
+	#[test]
+	fn test_cached_key_set() {
+		let server = StandInServer::default();
+		server.serve(Some("max-age=3600"), KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json").with_min_refresh_interval(Duration::from_secs(0));
+
+		assert!(server.requests() == 0);
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(sign("key-1", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(server.requests() == 1);
+	}
+
+	#[test]
+	fn test_expired_key_set() {
+		let server = StandInServer::default();
+		server.serve(Some("no-store"), KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json").with_min_refresh_interval(Duration::from_secs(0));
+
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		assert!(server.requests() == 2);
+	}
+
+	#[test]
+	fn test_refresh_unknown_kid() {
+		let server = StandInServer::default();
+		server.serve(Some("max-age=3600"), KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json").with_min_refresh_interval(Duration::from_secs(0));
+
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		assert!(server.requests() == 1);
+
+		// The key set is fetched again when a new key ID shows up.
+		server.serve(Some("max-age=3600"), KEY_2);
+		assert!(let Ok(_) = compact::decode_verify(sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(server.requests() == 2);
+
+		// Still unknown key IDs are reported as unsupported.
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(sign("key-3", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(server.requests() == 3);
+	}
+
+	#[test]
+	fn test_refresh_rate_limit() {
+		let server = StandInServer::default();
+		server.serve(None, KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json");
+
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		server.serve(None, KEY_2);
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(sign("key-3", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(server.requests() == 1);
+
+		// An explicit refresh is not rate limited.
+		jwks.refresh().unwrap();
+		assert!(server.requests() == 2);
+		assert!(let Ok(_) = compact::decode_verify(sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+	}
+
+	#[test]
+	fn test_fetch_error() {
+		let server = StandInServer::default();
+		server.serve(None, "not json");
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json");
+		assert!(let Err(Error { kind: Error::Other, .. }) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+
+		*server.response.lock().unwrap() = HttpResponse{status: 404, ..HttpResponse::default()};
+		assert!(let Err(Error { kind: Error::Other, .. }) = jwks.refresh());
+	}
+
+	#[test]
+	fn test_fetch_error_unknown_kid() {
+		let server = StandInServer::default();
+		server.serve(Some("max-age=3600"), KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json").with_min_refresh_interval(Duration::from_secs(0));
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+
+		// If the refresh for an unknown key ID fails, the cached key set reports the unknown key ID.
+		*server.response.lock().unwrap() = HttpResponse{status: 500, ..HttpResponse::default()};
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+		assert!(server.requests() == 2);
+	}
+
+	#[test]
+	fn test_verify_during_fetch() {
+		let server = StandInServer::default();
+		server.serve(Some("max-age=3600"), KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json");
+		assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+
+		let (open, gate) = mpsc::channel();
+		*server.gate.lock().unwrap() = Some(gate);
+		server.serve(Some("max-age=3600"), KEY_2);
+
+		std::thread::scope(|scope| {
+			let refresh = scope.spawn(|| jwks.refresh());
+			while server.requests() < 2 {
+				std::thread::yield_now();
+			}
+
+			// The cached key set is used while the refresh is blocked.
+			assert!(let Ok(_) = compact::decode_verify(sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms").as_bytes(), &jwks));
+			assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+
+			open.send(()).unwrap();
+			assert!(let Ok(()) = refresh.join().unwrap());
+		});
+
+		assert!(let Ok(_) = compact::decode_verify(sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!").as_bytes(), &jwks));
+		assert!(server.requests() == 2);
+		assert!(jwks.keys().unwrap().keys.len() == 2);
+	}
+
+	#[test]
+	fn test_verify_async() {
+		let server = StandInServer::default();
+		server.serve(Some("max-age=3600"), KEY_1);
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json").with_min_refresh_interval(Duration::from_secs(0));
+
+		// Concurrent verifications on a single thread share one fetch, and wait for it without blocking.
+		let signed = sign("key-1", b"this is a secret key that is long enough for all HMAC algorithms");
+		let (a, b) = futures::executor::block_on(futures::future::join(
+			compact::decode_verify_async(signed.as_bytes(), &jwks),
+			compact::decode_verify_async(signed.as_bytes(), &jwks),
+		));
+		assert!(let Ok(_) = a);
+		assert!(let Ok(_) = b);
+		assert!(server.requests() == 1);
+
+		server.serve(Some("max-age=3600"), KEY_2);
+		let signed = sign("key-2", b"this is another secret key that is long enough for HMAC-SHA-512!");
+		assert!(let Ok(_) = futures::executor::block_on(compact::decode_verify_async(signed.as_bytes(), &jwks)));
+		assert!(server.requests() == 2);
+
+		assert!(let Ok(()) = futures::executor::block_on(jwks.refresh_async()));
+		assert!(futures::executor::block_on(jwks.keys_async()).unwrap().keys.len() == 2);
+		assert!(server.requests() == 3);
+	}
+
+	/// Sign a message with an algorithm the library only supports for verification.
+	fn sign_public(kid: &str, algorithm: &'static str, compute: impl Fn(&[u8]) -> Vec<u8>) -> compact::EncodedSignedMessage {
+		struct PublicKeySigner<F>(&'static str, F);
+
+		impl<F: Fn(&[u8]) -> Vec<u8>> crate::Signer for PublicKeySigner<F> {
+			fn set_header_params(&self, header: &mut JsonObject) {
+				header.insert("alg".to_string(), self.0.into());
+			}
+
+			fn compute_mac(&self, encoded_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>> {
+				Ok((self.1)(&[encoded_header, b".", encoded_payload].concat()))
+			}
+		}
+
+		compact::encode_sign(json_object!{"kid": kid}, b"foo", &PublicKeySigner(algorithm, compute)).unwrap()
+	}
+
+	#[test]
+	fn test_verify_public_keys() {
+		use ::rsa::sha2::{Digest, Sha256};
+		use ::rsa::traits::PublicKeyParts;
+
+		let rsa_key = ::rsa::RsaPrivateKey::new(&mut rand_core::OsRng, 1024).unwrap();
+		let ec_key  = p256::ecdsa::SigningKey::random(&mut rand_core::OsRng);
+		let ec_point = ec_key.verifying_key().to_encoded_point(false);
+		let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+		let body = serde_json::json!({"keys": [
+			{"kty": "RSA", "kid": "rsa-1", "use": "sig", "alg": "RS256", "n": encode(&rsa_key.n().to_bytes_be()), "e": encode(&rsa_key.e().to_bytes_be())},
+			{"kty": "EC", "kid": "ec-1", "use": "sig", "crv": "P-256", "x": encode(ec_point.x().unwrap()), "y": encode(ec_point.y().unwrap())},
+		]});
+
+		let server = StandInServer::default();
+		server.serve(Some("max-age=3600"), &body.to_string());
+		let jwks = RemoteJwks::new(&server, "https://example.com/jwks.json");
+
+		let rs256 = sign_public("rsa-1", "RS256", |input| rsa_key.sign(::rsa::Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(input)).unwrap());
+		let es256 = sign_public("ec-1", "ES256", |input| {
+			let signature : p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(&ec_key, input);
+			signature.to_vec()
+		});
+		assert!(let Ok(_) = compact::decode_verify(rs256.as_bytes(), &jwks));
+		assert!(let Ok(_) = compact::decode_verify(es256.as_bytes(), &jwks));
+		assert!(let Ok(_) = futures::executor::block_on(compact::decode_verify_async(rs256.as_bytes(), &jwks)));
+		assert!(let Ok(_) = futures::executor::block_on(compact::decode_verify_async(es256.as_bytes(), &jwks)));
+
+		// Signatures from the wrong key are rejected.
+		let es256 = sign_public("rsa-1", "ES256", |input| {
+			let signature : p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(&ec_key, input);
+			signature.to_vec()
+		});
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(es256.as_bytes(), &jwks));
+		let rs256 = sign_public("ec-1", "RS256", |input| rsa_key.sign(::rsa::Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(input)).unwrap());
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = compact::decode_verify(rs256.as_bytes(), &jwks));
+		let es256 = sign_public("ec-1", "ES256", |input| {
+			let signature : p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(&p256::ecdsa::SigningKey::random(&mut rand_core::OsRng), input);
+			signature.to_vec()
+		});
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = compact::decode_verify(es256.as_bytes(), &jwks));
+		assert!(server.requests() == 1);
+	}
+}
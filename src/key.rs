@@ -0,0 +1,102 @@
+//! Handling of secret key material.
+
+use core::fmt;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use zeroize::Zeroize;
+
+/// Secret key material that can be held by signers, verifiers and encrypters.
+///
+/// Types holding a key call [`KeyMaterial::zeroize`] when they are dropped.
+/// Owned keys like `Vec<u8>`, `Box<[u8]>`, `String` and `[u8; N]` overwrite their contents with zeroes.
+/// Borrowed keys like `&[u8]` are left untouched: clearing them is the responsibility of the owner.
+pub trait KeyMaterial: AsRef<[u8]> {
+	/// Overwrite the key material with zeroes, if it is owned.
+	fn zeroize(&mut self);
+}
+
+impl KeyMaterial for Vec<u8> {
+	fn zeroize(&mut self) {
+		Zeroize::zeroize(self);
+	}
+}
+
+impl KeyMaterial for Box<[u8]> {
+	fn zeroize(&mut self) {
+		Zeroize::zeroize(self);
+	}
+}
+
+impl KeyMaterial for String {
+	fn zeroize(&mut self) {
+		Zeroize::zeroize(self);
+	}
+}
+
+impl<const N: usize> KeyMaterial for [u8; N] {
+	fn zeroize(&mut self) {
+		Zeroize::zeroize(self);
+	}
+}
+
+impl KeyMaterial for &[u8] {
+	fn zeroize(&mut self) {}
+}
+
+impl<const N: usize> KeyMaterial for &[u8; N] {
+	fn zeroize(&mut self) {}
+}
+
+impl KeyMaterial for &Vec<u8> {
+	fn zeroize(&mut self) {}
+}
+
+impl KeyMaterial for &str {
+	fn zeroize(&mut self) {}
+}
+
+impl KeyMaterial for &String {
+	fn zeroize(&mut self) {}
+}
+
+/// Placeholder for secret values in [`fmt::Debug`] output.
+pub(crate) struct Redacted;
+
+impl fmt::Debug for Redacted {
+	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+		formatter.write_str("<redacted>")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::assert;
+
+	#[test]
+	fn test_zeroize_owned() {
+		let mut key = b"secretkey".to_vec();
+		KeyMaterial::zeroize(&mut key);
+		assert!(key.is_empty());
+
+		let mut key = *b"secretkey";
+		KeyMaterial::zeroize(&mut key);
+		assert!(key == [0; 9]);
+	}
+
+	#[test]
+	fn test_zeroize_borrowed() {
+		let owner = b"secretkey".to_vec();
+		let mut key = owner.as_slice();
+		KeyMaterial::zeroize(&mut key);
+		assert!(owner == b"secretkey");
+	}
+
+	#[test]
+	fn test_redacted() {
+		assert!(format!("{:?}", Redacted) == "<redacted>");
+	}
+}
@@ -1,12 +1,33 @@
 //! This library provides JSON Web Signature encoding, decoding, signing and verification
 //! as described in [RFC 7515](https://tools.ietf.org/html/rfc7515).
 //!
-//! Currently, encoding and decoding is available only for the JWS Compact Serialization scheme in the
-//! [`compact`] module.
+//! Encoding and decoding is available for the JWS Compact Serialization scheme in the [`compact`] module,
+//! and for the general JWS JSON Serialization with multiple signatures in the [`json`] module.
 //!
 //! Signing and verifying is done through the [`Signer`] and [`Verifier`] traits.
+//! Verifiers that need to look up keys asynchronously can implement the [`AsyncVerifier`] trait instead.
+//! Signing can be delegated to an external key holder through the [`backend`] module.
+//! With the `pkcs11` feature, the `pkcs11` module provides a backend for keys held by a PKCS#11 token.
 //! The [`hmac`] module contains implementations for these traits that support the HMAC-SHA2 family of algorithms.
 //!
+//! Keys can be fetched from a remote JWK set with the [`jwks::RemoteJwks`] verifier.
+//! It supports symmetric (`oct`) keys with HMAC, and with the `std` feature also `RSA`, `EC` (`P-256`) and `OKP` (`Ed25519`) public keys.
+//! Signing and verification can be audited by installing a global hook with [`observer::set_observer`].
+//!
+//! JSON Web Encryption as described in [RFC 7516](https://tools.ietf.org/html/rfc7516) is available in the [`jwe`] module.
+//!
+//! # Features:
+//! The `std` feature is enabled by default.
+//! Without it, the library is `no_std` and only needs `alloc`.
+//! The [`compact`], [`json`], [`hmac`], [`jwk`] and [`backend`] modules and the signing and verification traits are always available.
+//! The [`jwe`], [`jwks`] and [`observer`] modules require the `std` feature.
+//! The `wasm-bindgen` feature adds a `wasm` module with JavaScript bindings for WebAssembly.
+//! The `cdylib` feature adds an `ffi` module with a C API, declared in the `include/jws.h` header.
+//! The `python` feature adds a `python` module with Python bindings using PyO3.
+//! The `http` feature adds an `http_signature` module to sign and verify HTTP messages with detached signatures in the Open Banking `x-jws-signature` profile.
+//! The `tower` feature adds a `tower` module with a middleware layer that verifies bearer tokens.
+//! The `tracing` feature instruments signing and verification with [`tracing`](https://docs.rs/tracing) spans and events.
+//!
 //! # Example:
 //! ```
 //! use jws::{JsonObject, JsonValue};
@@ -18,11 +39,16 @@
 //!   let mut header = JsonObject::new();
 //!   header.insert(String::from("typ"), JsonValue::from("text/plain"));
 //!
+//!   // Create the signer and verifier, which checks that the key is long enough.
+//!   let key      = b"this is a secret key that is long enough for all HMAC algorithms";
+//!   let signer   = Hs512Signer::new(key)?;
+//!   let verifier = HmacVerifier::new(key)?;
+//!
 //!   // Encode and sign the message.
-//!   let encoded = encode_sign(header, b"payload", &Hs512Signer::new(b"secretkey"))?;
+//!   let encoded = encode_sign(header, b"payload", &signer)?;
 //!
 //!   // Decode and verify the message.
-//!   let decoded = decode_verify(encoded.data().as_bytes(), &HmacVerifier::new(b"secretkey"))?;
+//!   let decoded = decode_verify(encoded.data().as_bytes(), &verifier)?;
 //!
 //!   assert_eq!(decoded.payload, b"payload");
 //!   assert_eq!(decoded.header.get("typ").and_then(|x| x.as_str()), Some("text/plain"));
@@ -31,22 +57,61 @@
 //! }
 //!
 //! ```
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
 
+pub mod backend;
 pub mod compact;
 pub mod hmac;
+#[cfg(feature = "std")]
+pub mod jwe;
+pub mod json;
+pub mod jwk;
+#[cfg(feature = "std")]
+pub mod jwks;
 mod error;
 mod header;
+mod key;
 mod combine;
 pub mod none;
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "tracing")]
+mod trace;
+
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
+
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+
+#[cfg(feature = "python")]
+pub mod python;
 
-pub use crate::error::{Error, ErrorKind, Result};
+#[cfg(feature = "http")]
+pub mod http_signature;
+
+#[cfg(feature = "tower")]
+pub mod tower;
+
+pub use crate::error::{Error, ErrorKind, MessagePart, Result};
 pub use crate::header::{get_header_param, get_required_header_param, parse_required_header_param};
+pub use crate::key::KeyMaterial;
+pub use crate::combine::{ThresholdVerifier, VerificationReport, VerifierOutcome};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::future::Future;
 
 /// Re-exported [`serde_json::Value`].
 pub type JsonValue  = serde_json::Value;
 
 /// A JSON object.
-pub type JsonObject = std::collections::BTreeMap<String, JsonValue>;
+pub type JsonObject = alloc::collections::BTreeMap<String, JsonValue>;
 
 /// Create a JSON object.
 ///
@@ -67,17 +132,23 @@ macro_rules! json_object {
 
 	{ $( $name:tt : $value:expr, )+ } => {{
 		let mut object = $crate::JsonObject::new();
-		$(object.insert(String::from($name), $crate::JsonValue::from($value));)*
+		$(object.insert($crate::__private::String::from($name), $crate::JsonValue::from($value));)*
 		object
 	}};
 
 	{ $( $name:tt : $value:expr ),+ } => {{
 		let mut object = $crate::JsonObject::new();
-		$(object.insert(String::from($name), $crate::JsonValue::from($value));)*
+		$(object.insert($crate::__private::String::from($name), $crate::JsonValue::from($value));)*
 		object
 	}};
 }
 
+/// Re-exports for use in macros.
+#[doc(hidden)]
+pub mod __private {
+	pub use alloc::string::String;
+}
+
 /// A verifier for JWS messages.
 pub trait Verifier: Sized {
 	/// Verify the signature of a JWS message.
@@ -104,6 +175,32 @@ pub trait Verifier: Sized {
 		signature          : &[u8],
 	) -> Result<()>;
 
+	/// Verify the signature of a JWS message and add the outcome of every underlying verifier to a report.
+	///
+	/// This is a diagnostic variant of [`Verifier::verify`] that returns the same result.
+	/// Combined verifiers created with [`Verifier::or`] and [`Verifier::and`] add the outcome of each verifier they tried.
+	/// The default implementation adds the result of [`Verifier::verify`] as a single outcome, labelled with [`Verifier::label`].
+	fn verify_report(
+		&self,
+		protected_header   : Option<&JsonObject>,
+		unprotected_header : Option<&JsonObject>,
+		encoded_header     : &[u8],
+		encoded_payload    : &[u8],
+		signature          : &[u8],
+		report             : &mut VerificationReport,
+	) -> Result<()> {
+		let result = self.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature);
+		report.push(core::any::type_name::<Self>(), self.label(), result.clone());
+		result
+	}
+
+	/// Get a label that identifies the key of this verifier in a [`VerificationReport`], like a key ID.
+	///
+	/// The default implementation returns `None`.
+	fn label(&self) -> Option<&str> {
+		None
+	}
+
 	/// Create a new verifier that accepts a message if either this or the other verifier does.
 	fn or<Other: Verifier>(self, other: Other) -> combine::OrVerifier<Self, Other> {
 		combine::OrVerifier::new(self, other)
@@ -115,6 +212,39 @@ pub trait Verifier: Sized {
 	}
 }
 
+/// A verifier for JWS messages that needs to perform asynchronous operations.
+///
+/// This is useful for verifiers that need to look up keys from a remote source, like a JWKS endpoint or a key management service.
+/// Use [`compact::decode_verify_async`] to decode and verify a message with an asynchronous verifier.
+///
+/// Synchronous [`Verifier`] implementations can be used as asynchronous verifiers by wrapping them in [`Blocking`].
+pub trait AsyncVerifier {
+	/// Verify the signature of a JWS message.
+	///
+	/// The arguments and the returned errors are the same as for [`Verifier::verify`].
+	fn verify_async(
+		&self,
+		protected_header   : Option<&JsonObject>,
+		unprotected_header : Option<&JsonObject>,
+		encoded_header     : &[u8],
+		encoded_payload    : &[u8],
+		signature          : &[u8],
+	) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl<V: Verifier + Sync> AsyncVerifier for Blocking<V> {
+	fn verify_async(
+		&self,
+		protected_header   : Option<&JsonObject>,
+		unprotected_header : Option<&JsonObject>,
+		encoded_header     : &[u8],
+		encoded_payload    : &[u8],
+		signature          : &[u8],
+	) -> impl Future<Output = Result<()>> + Send {
+		core::future::ready(self.0.verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature))
+	}
+}
+
 /// A signer for JWS messages.
 pub trait Signer {
 	/// Set the header parameters to indicate how the message should be verified.
@@ -126,4 +256,62 @@ pub trait Signer {
 	///
 	/// The returned MAC must be plain bytes, not hex or base64 encoded.
 	fn compute_mac(&self, encoded_protected_header: &[u8], encoded_payload: &[u8]) -> Result<Vec<u8>>;
+
+	/// Create a new signer that adds a signature for both this and the other signer.
+	///
+	/// Messages with multiple signatures can be encoded with [`json::encode_sign`].
+	fn and<Other: MultiSigner>(self, other: Other) -> combine::AndSigner<Self, Other> where Self: Sized {
+		combine::AndSigner::new(self, other)
+	}
+}
+
+/// One or more signers that each add a signature to a message.
+///
+/// All [`Signer`] implementations are multi-signers with a single signer.
+/// Use [`Signer::and`] to combine signers.
+pub trait MultiSigner {
+	/// Get the individual signers, in the order their signatures should be added.
+	fn signers(&self) -> Vec<&dyn Signer>;
+}
+
+impl<S: Signer> MultiSigner for S {
+	fn signers(&self) -> Vec<&dyn Signer> {
+		alloc::vec![self]
+	}
+}
+
+/// A signer for JWS messages that needs to perform asynchronous operations.
+///
+/// This is useful for signers that delegate to a remote key holder, like a key management service.
+/// Use [`compact::encode_sign_async`] to encode and sign a message with an asynchronous signer.
+///
+/// Synchronous [`Signer`] implementations can be used as asynchronous signers by wrapping them in [`Blocking`].
+pub trait AsyncSigner {
+	/// Set the header parameters to indicate how the message should be verified.
+	///
+	/// See [`Signer::set_header_params`].
+	/// The method has a different name so that it does not clash with [`Signer::set_header_params`] for types that implement both traits.
+	fn set_header_params_async(&self, header: &mut JsonObject);
+
+	/// Compute the Message Authentication Code for the encoded protected header and encoded payload.
+	///
+	/// See [`Signer::compute_mac`].
+	fn compute_mac_async(&self, encoded_protected_header: &[u8], encoded_payload: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send;
 }
+
+impl<S: Signer + Sync> AsyncSigner for Blocking<S> {
+	fn set_header_params_async(&self, header: &mut JsonObject) {
+		self.0.set_header_params(header)
+	}
+
+	fn compute_mac_async(&self, encoded_protected_header: &[u8], encoded_payload: &[u8]) -> impl Future<Output = Result<Vec<u8>>> + Send {
+		core::future::ready(self.0.compute_mac(encoded_protected_header, encoded_payload))
+	}
+}
+
+/// Adapter to use a synchronous [`Verifier`] or [`Signer`] where an asynchronous one is expected.
+///
+/// The wrapped verifier or signer runs directly inside the returned future, so it must not block for long.
+/// Verifiers that perform blocking I/O should be given a proper [`AsyncVerifier`] implementation instead.
+#[derive(Clone, Debug)]
+pub struct Blocking<T>(pub T);
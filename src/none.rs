@@ -5,7 +5,10 @@
 //!
 //! It doesn't often make sense to use this "algorithm".
 
-use crate::{Error, JsonObject, JsonValue, parse_required_header_param, Result, Signer, Verifier};
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use crate::{Error, JsonObject, JsonValue, MessagePart, parse_required_header_param, Result, Signer, Verifier};
 
 /// Message verifier for the `none` algorithm.
 ///
@@ -25,9 +28,9 @@ impl Verifier for NoneVerifier {
 		let algorithm : &str = parse_required_header_param(protected_header, unprotected_header, "alg")?;
 
 		if algorithm != "none" {
-			Err(Error::unsupported_mac_algorithm(algorithm))
+			Err(Error::unsupported_mac_algorithm(algorithm).with_header_param("alg").with_algorithm(algorithm))
 		} else if !signature.is_empty() {
-			Err(Error::invalid_signature(""))
+			Err(Error::invalid_signature("").with_part(MessagePart::Signature).with_algorithm(algorithm))
 		} else {
 			Ok(())
 		}
@@ -0,0 +1,174 @@
+//! Observer hooks for auditing signing and verification.
+//!
+//! A global [`Observer`] can be installed with [`set_observer`].
+//! It receives an [`Event`] for every message signed with [`crate::compact::encode_sign`]
+//! and every message verified with [`crate::compact::decode_verify`], including their async, batch and report variants,
+//! It also receives an event for every signature added with [`crate::json::encode_sign`],
+//! and for every message verified with [`crate::json::decode_verify`] and [`crate::json::decode_verify_threshold`].
+//!
+//! Events contain the algorithm, the key ID, the outcome and the duration of the operation.
+//! For verified messages with multiple signatures, the algorithm and key ID of the first valid signature are reported.
+//! They never contain key material, the payload or the signature.
+//!
+//! This module requires the `std` feature.
+//!
+//! # Example:
+//! ```
+//! jws::observer::set_observer(|event: &jws::observer::Event<'_>| {
+//!   eprintln!("{:?} alg={:?} kid={:?} ok={} in {:?}", event.operation, event.algorithm, event.kid, event.result.is_ok(), event.duration);
+//! });
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::{Error, JsonObject};
+
+static OBSERVER: RwLock<Option<Arc<dyn Observer>>> = RwLock::new(None);
+
+/// Set when an observer is installed, so operations don't need to take the lock when there is none.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// An observer for signing and verification operations.
+///
+/// Observers are called synchronously on the thread that performed the operation,
+/// so they should return quickly.
+pub trait Observer: Send + Sync {
+	/// Called after a message was signed or verified, or failed to be.
+	fn observe(&self, event: &Event<'_>);
+}
+
+impl<F: Fn(&Event<'_>) + Send + Sync> Observer for F {
+	fn observe(&self, event: &Event<'_>) {
+		self(event)
+	}
+}
+
+/// The kind of operation that was performed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+	/// A message was signed.
+	Sign,
+
+	/// A message was verified.
+	Verify,
+}
+
+/// A signing or verification operation reported to an [`Observer`].
+#[derive(Copy, Clone, Debug)]
+pub struct Event<'a> {
+	/// The operation that was performed.
+	pub operation : Operation,
+
+	/// The `alg` header parameter, if the header could be decoded and has one.
+	pub algorithm : Option<&'a str>,
+
+	/// The `kid` header parameter, if the header could be decoded and has one.
+	pub kid       : Option<&'a str>,
+
+	/// The outcome of the operation.
+	pub result    : core::result::Result<(), &'a Error>,
+
+	/// The time it took to perform the operation.
+	pub duration  : Duration,
+}
+
+/// Install the global observer, replacing the previous one.
+pub fn set_observer(observer: impl Observer + 'static) {
+	let mut current = OBSERVER.write().unwrap_or_else(|e| e.into_inner());
+	*current = Some(Arc::new(observer));
+	INSTALLED.store(true, Ordering::Release);
+}
+
+/// Remove the global observer.
+pub fn clear_observer() {
+	let mut current = OBSERVER.write().unwrap_or_else(|e| e.into_inner());
+	*current = None;
+	INSTALLED.store(false, Ordering::Release);
+}
+
+fn current_observer() -> Option<Arc<dyn Observer>> {
+	if !INSTALLED.load(Ordering::Acquire) {
+		return None;
+	}
+	OBSERVER.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// An operation in progress that is reported to the global observer when finished.
+///
+/// If no observer is installed when the operation starts, nothing is reported and no time is measured.
+pub(crate) struct Observation {
+	operation : Operation,
+	observer  : Option<(Arc<dyn Observer>, Instant)>,
+}
+
+impl Observation {
+	pub(crate) fn start(operation: Operation) -> Self {
+		let observer = current_observer().map(|observer| (observer, Instant::now()));
+		Self{operation, observer}
+	}
+
+	/// Report the outcome of the operation with the header of the message, if it is known.
+	pub(crate) fn finish(self, header: Option<&JsonObject>, result: core::result::Result<(), &Error>) {
+		if let Some((observer, start)) = self.observer {
+			let param = |name| header.and_then(|header| header.get(name)).and_then(|value| value.as_str());
+			observer.observe(&Event {
+				operation : self.operation,
+				algorithm : param("alg"),
+				kid       : param("kid"),
+				result,
+				duration  : start.elapsed(),
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{compact, json, json_object, ThresholdVerifier};
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+
+	use assert2::assert;
+	use std::sync::Mutex;
+
+	const KEY : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+
+	#[test]
+	fn test_observer() {
+		// Other tests may run concurrently, so only record events for our own key ID.
+		let events = Arc::new(Mutex::new(Vec::new()));
+		set_observer({
+			let events = events.clone();
+			move |event: &Event<'_>| {
+				if event.kid == Some("observer-test") {
+					events.lock().unwrap().push((event.operation, event.algorithm.map(String::from), event.result.map_err(|e| e.kind())));
+				}
+			}
+		});
+
+		let signed = compact::encode_sign(json_object!{"kid": "observer-test"}, b"foo", &Hs256Signer::new(KEY).unwrap()).unwrap();
+		let verifier = HmacVerifier::new(KEY).unwrap();
+		assert!(let Ok(_) = compact::decode_verify(signed.as_bytes(), &verifier));
+		assert!(let Err(_) = compact::decode_verify(signed.data().replace(".Zm9v.", ".YmFy.").as_bytes(), &verifier));
+		assert!(let (Ok(_), _) = compact::decode_verify_report(signed.as_bytes(), &verifier));
+
+		let signed_json = json::encode_sign(json_object!{"kid": "observer-test"}, b"foo", &Hs256Signer::new(KEY).unwrap()).unwrap();
+		assert!(let Ok(_) = json::decode_verify(signed_json.as_bytes(), &verifier));
+		assert!(let Ok(_) = json::decode_verify_threshold(signed_json.as_bytes(), &ThresholdVerifier::new(1, vec![verifier.clone()]).unwrap()));
+
+		clear_observer();
+		assert!(let Ok(_) = compact::decode_verify(signed.as_bytes(), &verifier));
+
+		let events = events.lock().unwrap();
+		assert!(events.len() == 7);
+		assert!(events[0] == (Operation::Sign, Some(String::from("HS256")), Ok(())));
+		assert!(events[1] == (Operation::Verify, Some(String::from("HS256")), Ok(())));
+		assert!(events[2] == (Operation::Verify, Some(String::from("HS256")), Err(Error::InvalidSignature)));
+		assert!(events[3] == (Operation::Verify, Some(String::from("HS256")), Ok(())));
+		assert!(events[4] == (Operation::Sign, Some(String::from("HS256")), Ok(())));
+		assert!(events[5] == (Operation::Verify, Some(String::from("HS256")), Ok(())));
+		assert!(events[6] == (Operation::Verify, Some(String::from("HS256")), Ok(())));
+	}
+}
@@ -0,0 +1,352 @@
+//! [`SigningBackend`] implementation for keys held by a PKCS#11 token.
+//!
+//! This module is only available with the `pkcs11` feature.
+//! It uses the [`cryptoki`](https://docs.rs/cryptoki) crate to talk to a PKCS#11 module, like a hardware security module or SoftHSM.
+//! The `cryptoki` crate is re-exported for convenience.
+//!
+//! The following algorithms are supported:
+//!   - RS256, RS384 and RS512 with RSA private keys.
+//!   - ES256, ES384 and ES512 with EC private keys on the P-256, P-384 and P-521 curves.
+//!     The message is hashed in software and the digest is signed by the token.
+//!   - HS256, HS384 and HS512 with generic secret keys.
+//!
+//! # Example:
+//! ```no_run
+//! use jws::backend::BackendSigner;
+//! use jws::compact::encode_sign;
+//! use jws::pkcs11::Pkcs11Backend;
+//! use jws::pkcs11::cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+//! use jws::pkcs11::cryptoki::session::UserType;
+//! use jws::pkcs11::cryptoki::types::AuthPin;
+//!
+//! let pkcs11 = Pkcs11::new("/usr/lib/softhsm/libsofthsm2.so").unwrap();
+//! pkcs11.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK)).unwrap();
+//! let slot = pkcs11.get_slots_with_token().unwrap()[0];
+//! let session = pkcs11.open_ro_session(slot).unwrap();
+//! session.login(UserType::User, Some(&AuthPin::new("1234".into()))).unwrap();
+//!
+//! let signer = BackendSigner::new(Pkcs11Backend::find(session, "release-signing-key").unwrap());
+//! let signed = encode_sign(jws::JsonObject::new(), b"manifest", &signer).unwrap();
+//! println!("{}", signed.data());
+//! ```
+
+use std::sync::Mutex;
+
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::Session;
+use sha2::Digest as _;
+
+use crate::backend::{BackendKey, SigningBackend};
+use crate::{Error, Result};
+
+pub use cryptoki;
+
+/// DER encoded object identifiers of the supported curves, as found in the `CKA_EC_PARAMS` attribute.
+const OID_P256 : &[u8] = &[0x06, 0x08, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const OID_P384 : &[u8] = &[0x06, 0x05, 0x2B, 0x81, 0x04, 0x00, 0x22];
+const OID_P521 : &[u8] = &[0x06, 0x05, 0x2B, 0x81, 0x04, 0x00, 0x23];
+
+/// A signing backend that uses a key held by a PKCS#11 token.
+///
+/// The session must be logged in if the key is a private object.
+/// The session is protected by a mutex, so the backend can be shared between threads.
+pub struct Pkcs11Backend {
+	session   : Mutex<Session>,
+	key       : ObjectHandle,
+	key_type  : KeyType,
+	algorithm : String,
+	key_id    : Option<String>,
+}
+
+impl Pkcs11Backend {
+	/// Create a new backend for a key object.
+	///
+	/// The `alg` header parameter is determined from the `CKA_KEY_TYPE` and `CKA_EC_PARAMS` attributes of the key.
+	/// RSA keys and secret keys default to RS256 and HS256, which can be changed with [`Self::with_algorithm`].
+	///
+	/// The `kid` header parameter is taken from the `CKA_ID` attribute (hex encoded) or else the `CKA_LABEL` attribute.
+	pub fn new(session: Session, key: ObjectHandle) -> Result<Self> {
+		let attributes = session.get_attributes(key, &[AttributeType::KeyType, AttributeType::EcParams, AttributeType::Id, AttributeType::Label])
+			.map_err(pkcs11_error)?;
+
+		let mut key_type  = None;
+		let mut ec_params = None;
+		let mut id        = None;
+		let mut label     = None;
+		for attribute in attributes {
+			match attribute {
+				Attribute::KeyType(x)  => key_type  = Some(x),
+				Attribute::EcParams(x) => ec_params = Some(x),
+				Attribute::Id(x)       => id        = Some(x),
+				Attribute::Label(x)    => label     = Some(x),
+				_                      => (),
+			}
+		}
+
+		let key_type  = key_type.ok_or_else(|| Error::other("PKCS#11 key object has no key type"))?;
+		let algorithm = default_algorithm(key_type, ec_params.as_deref())?;
+		let key_id    = match (id, label) {
+			(Some(id), _) if !id.is_empty() => Some(id.iter().map(|x| format!("{:02x}", x)).collect()),
+			(_, Some(label)) if !label.is_empty() => Some(String::from_utf8_lossy(&label).into_owned()),
+			_ => None,
+		};
+
+		Ok(Self{session: Mutex::new(session), key, key_type, algorithm: algorithm.to_string(), key_id})
+	}
+
+	/// Create a new backend for the private or secret key with a specific `CKA_LABEL` attribute.
+	pub fn find(session: Session, label: &str) -> Result<Self> {
+		let mut keys = Vec::new();
+		for class in &[ObjectClass::PRIVATE_KEY, ObjectClass::SECRET_KEY] {
+			let template = [Attribute::Class(*class), Attribute::Label(label.as_bytes().to_vec()), Attribute::Sign(true)];
+			keys.extend(session.find_objects(&template).map_err(pkcs11_error)?);
+		}
+
+		match keys.as_slice() {
+			[key] => Self::new(session, *key),
+			[]    => Err(Error::other(format!("no PKCS#11 signing key found with label {:?}", label))),
+			_     => Err(Error::other(format!("multiple PKCS#11 signing keys found with label {:?}", label))),
+		}
+	}
+
+	/// Use a different algorithm with the key.
+	///
+	/// This can be used to select RS384 or RS512 for RSA keys, or HS384 or HS512 for secret keys.
+	/// The algorithm must be supported for the key type.
+	pub fn with_algorithm(mut self, algorithm: &str) -> Result<Self> {
+		mechanism(self.key_type, algorithm)?;
+		self.algorithm = algorithm.to_string();
+		Ok(self)
+	}
+
+	/// Use a different key ID for the `kid` header parameter.
+	pub fn with_key_id(mut self, key_id: impl Into<String>) -> Self {
+		self.key_id = Some(key_id.into());
+		self
+	}
+}
+
+impl BackendKey for Pkcs11Backend {
+	fn algorithm(&self) -> &str {
+		&self.algorithm
+	}
+
+	fn key_id(&self) -> Option<&str> {
+		self.key_id.as_deref()
+	}
+}
+
+impl SigningBackend for Pkcs11Backend {
+	fn sign(&self, signing_input: &[u8]) -> Result<Vec<u8>> {
+		let (mechanism, digest) = mechanism(self.key_type, &self.algorithm)?;
+
+		// For ECDSA, the token signs a digest that is computed here.
+		let data = match digest {
+			None                         => signing_input.to_vec(),
+			Some(SoftwareDigest::Sha256) => sha2::Sha256::digest(signing_input).to_vec(),
+			Some(SoftwareDigest::Sha384) => sha2::Sha384::digest(signing_input).to_vec(),
+			Some(SoftwareDigest::Sha512) => sha2::Sha512::digest(signing_input).to_vec(),
+		};
+
+		// The session is never left in an inconsistent state, so a poisoned lock is fine.
+		let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+		session.sign(&mechanism, self.key, &data).map_err(pkcs11_error)
+	}
+}
+
+impl std::fmt::Debug for Pkcs11Backend {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("Pkcs11Backend")
+			.field("key", &self.key)
+			.field("key_type", &self.key_type)
+			.field("algorithm", &self.algorithm)
+			.field("key_id", &self.key_id)
+			.finish()
+	}
+}
+
+/// A digest to compute in software before signing.
+#[derive(Copy, Clone, Debug)]
+enum SoftwareDigest {
+	Sha256,
+	Sha384,
+	Sha512,
+}
+
+/// Determine the default JWS algorithm for a key.
+fn default_algorithm(key_type: KeyType, ec_params: Option<&[u8]>) -> Result<&'static str> {
+	if key_type == KeyType::RSA {
+		Ok("RS256")
+	} else if key_type == KeyType::EC {
+		match ec_params {
+			Some(OID_P256) => Ok("ES256"),
+			Some(OID_P384) => Ok("ES384"),
+			Some(OID_P521) => Ok("ES512"),
+			_              => Err(Error::unsupported_mac_algorithm("unsupported curve for PKCS#11 EC key")),
+		}
+	} else if key_type == KeyType::GENERIC_SECRET || key_type == KeyType::SHA256_HMAC {
+		Ok("HS256")
+	} else {
+		Err(Error::unsupported_mac_algorithm(format!("unsupported PKCS#11 key type: {}", key_type)))
+	}
+}
+
+/// Get the PKCS#11 mechanism and optional software digest for a JWS algorithm.
+fn mechanism(key_type: KeyType, algorithm: &str) -> Result<(Mechanism<'static>, Option<SoftwareDigest>)> {
+	let is_rsa    = key_type == KeyType::RSA;
+	let is_ec     = key_type == KeyType::EC;
+	let is_secret = key_type == KeyType::GENERIC_SECRET || key_type == KeyType::SHA256_HMAC;
+
+	match algorithm {
+		"RS256" if is_rsa    => Ok((Mechanism::Sha256RsaPkcs, None)),
+		"RS384" if is_rsa    => Ok((Mechanism::Sha384RsaPkcs, None)),
+		"RS512" if is_rsa    => Ok((Mechanism::Sha512RsaPkcs, None)),
+		"ES256" if is_ec     => Ok((Mechanism::Ecdsa, Some(SoftwareDigest::Sha256))),
+		"ES384" if is_ec     => Ok((Mechanism::Ecdsa, Some(SoftwareDigest::Sha384))),
+		"ES512" if is_ec     => Ok((Mechanism::Ecdsa, Some(SoftwareDigest::Sha512))),
+		"HS256" if is_secret => Ok((Mechanism::Sha256Hmac, None)),
+		"HS384" if is_secret => Ok((Mechanism::Sha384Hmac, None)),
+		"HS512" if is_secret => Ok((Mechanism::Sha512Hmac, None)),
+		_ => Err(Error::unsupported_mac_algorithm(format!("{} is not supported for PKCS#11 key type {}", algorithm, key_type))),
+	}
+}
+
+fn pkcs11_error(error: cryptoki::error::Error) -> Error {
+	Error::other(format!("PKCS#11 error: {}", error))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::backend::BackendSigner;
+	use crate::hmac::HmacVerifier;
+	use crate::{compact, json_object};
+	use assert2::assert;
+
+	#[test]
+	fn test_default_algorithm() {
+		assert!(default_algorithm(KeyType::RSA, None).unwrap() == "RS256");
+		assert!(default_algorithm(KeyType::EC, Some(OID_P256)).unwrap() == "ES256");
+		assert!(default_algorithm(KeyType::EC, Some(OID_P384)).unwrap() == "ES384");
+		assert!(default_algorithm(KeyType::EC, Some(OID_P521)).unwrap() == "ES512");
+		assert!(default_algorithm(KeyType::GENERIC_SECRET, None).unwrap() == "HS256");
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = default_algorithm(KeyType::EC, None));
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = default_algorithm(KeyType::AES, None));
+	}
+
+	#[test]
+	fn test_mechanism() {
+		assert!(let Ok((Mechanism::Sha384RsaPkcs, None)) = mechanism(KeyType::RSA, "RS384"));
+		assert!(let Ok((Mechanism::Ecdsa, Some(SoftwareDigest::Sha256))) = mechanism(KeyType::EC, "ES256"));
+		assert!(let Ok((Mechanism::Sha512Hmac, None)) = mechanism(KeyType::GENERIC_SECRET, "HS512"));
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = mechanism(KeyType::RSA, "ES256"));
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = mechanism(KeyType::EC, "PS256"));
+	}
+
+	/// Open a logged in session on a SoftHSM token.
+	///
+	/// The module path and user PIN are taken from the `JWS_TEST_PKCS11_MODULE` and `JWS_TEST_PKCS11_PIN` environment variables.
+	fn softhsm_session() -> Session {
+		use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+		use cryptoki::session::UserType;
+		use cryptoki::types::AuthPin;
+
+		let module = std::env::var("JWS_TEST_PKCS11_MODULE").expect("JWS_TEST_PKCS11_MODULE must be set");
+		let pin    = std::env::var("JWS_TEST_PKCS11_PIN").unwrap_or_else(|_| String::from("1234"));
+
+		let pkcs11 = Pkcs11::new(module).unwrap();
+		if let Err(e) = pkcs11.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK)) {
+			assert!(let cryptoki::error::Error::Pkcs11(cryptoki::error::RvError::CryptokiAlreadyInitialized, _) = e);
+		}
+		let slot    = pkcs11.get_slots_with_token().unwrap()[0];
+		let session = pkcs11.open_rw_session(slot).unwrap();
+		if let Err(e) = session.login(UserType::User, Some(&AuthPin::new(pin.into()))) {
+			assert!(let cryptoki::error::Error::Pkcs11(cryptoki::error::RvError::UserAlreadyLoggedIn, _) = e);
+		}
+		session
+	}
+
+	#[test]
+	#[ignore = "requires SoftHSM, set JWS_TEST_PKCS11_MODULE to run"]
+	fn test_softhsm_hmac() {
+		let session = softhsm_session();
+		let key = session.create_object(&[
+			Attribute::Class(ObjectClass::SECRET_KEY),
+			Attribute::KeyType(KeyType::GENERIC_SECRET),
+			Attribute::Token(false),
+			Attribute::Sign(true),
+			Attribute::Id(vec![0x01, 0xab]),
+			Attribute::Value(b"this is a secret key that is long enough for all HMAC algorithms".to_vec()),
+		]).unwrap();
+
+		let backend = Pkcs11Backend::new(session, key).unwrap().with_algorithm("HS512").unwrap();
+		assert!(backend.key_id() == Some("01ab"));
+
+		let signed  = compact::encode_sign(json_object!{}, b"foo", &BackendSigner::new(backend)).unwrap();
+		let decoded = compact::decode_verify(signed.as_bytes(), &HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms").unwrap()).unwrap();
+		assert!(decoded.header == json_object!{"alg": "HS512", "kid": "01ab"});
+	}
+
+	#[test]
+	#[ignore = "requires SoftHSM, set JWS_TEST_PKCS11_MODULE to run"]
+	fn test_softhsm_ecdsa() {
+		use p256::ecdsa::signature::Verifier as _;
+
+		let session = softhsm_session();
+		let (public_key, private_key) = session.generate_key_pair(
+			&Mechanism::EccKeyPairGen,
+			&[Attribute::Token(false), Attribute::Verify(true), Attribute::EcParams(OID_P256.to_vec())],
+			&[Attribute::Token(false), Attribute::Sign(true), Attribute::Private(true), Attribute::Label(b"jws-test-ec".to_vec())],
+		).unwrap();
+
+		// The EC point is a DER encoded octet string containing the SEC1 encoded point.
+		let point = match &session.get_attributes(public_key, &[AttributeType::EcPoint]).unwrap()[..] {
+			[Attribute::EcPoint(x)] => x[2..].to_vec(),
+			x => panic!("unexpected attributes: {:?}", x),
+		};
+		let verifying_key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).unwrap();
+
+		let backend = Pkcs11Backend::new(session, private_key).unwrap();
+		assert!(backend.algorithm() == "ES256");
+		assert!(backend.key_id() == Some("jws-test-ec"));
+
+		let signed = compact::encode_sign(json_object!{}, b"foo", &BackendSigner::new(backend)).unwrap();
+		let (_, signature) = compact::decode_unverified(signed.as_bytes()).unwrap();
+		let signature = p256::ecdsa::Signature::from_slice(&signature).unwrap();
+		let signing_input = format!("{}.{}", signed.header(), signed.payload());
+		assert!(let Ok(()) = verifying_key.verify(signing_input.as_bytes(), &signature));
+	}
+
+	#[test]
+	#[ignore = "requires SoftHSM, set JWS_TEST_PKCS11_MODULE to run"]
+	fn test_softhsm_rsa() {
+		use sha2::Digest as _;
+
+		let session = softhsm_session();
+		let (public_key, private_key) = session.generate_key_pair(
+			&Mechanism::RsaPkcsKeyPairGen,
+			&[Attribute::Token(false), Attribute::Verify(true), Attribute::ModulusBits(2048.into()), Attribute::PublicExponent(vec![1, 0, 1])],
+			&[Attribute::Token(false), Attribute::Sign(true), Attribute::Private(true), Attribute::Label(b"jws-test-rsa".to_vec())],
+		).unwrap();
+
+		let modulus = match &session.get_attributes(public_key, &[AttributeType::Modulus]).unwrap()[..] {
+			[Attribute::Modulus(x)] => x.clone(),
+			x => panic!("unexpected attributes: {:?}", x),
+		};
+		let public_key = ::rsa::RsaPublicKey::new(::rsa::BigUint::from_bytes_be(&modulus), ::rsa::BigUint::from(65537u32)).unwrap();
+
+		let backend = Pkcs11Backend::new(session, private_key).unwrap().with_algorithm("RS384").unwrap();
+		let signed = compact::encode_sign(json_object!{}, b"foo", &BackendSigner::new(backend)).unwrap();
+		let (decoded, signature) = compact::decode_unverified(signed.as_bytes()).unwrap();
+		assert!(decoded.header == json_object!{"alg": "RS384", "kid": "jws-test-rsa"});
+
+		// PKCS#1 v1.5 padding with the DER encoded DigestInfo prefix for SHA-384.
+		let padding = ::rsa::Pkcs1v15Sign {
+			hash_len: Some(48),
+			prefix: Box::new([0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30]),
+		};
+		let digest = sha2::Sha384::digest(format!("{}.{}", signed.header(), signed.payload()).as_bytes());
+		assert!(let Ok(()) = public_key.verify(padding, &digest, &signature));
+	}
+}
@@ -0,0 +1,199 @@
+//! Python bindings using [PyO3](https://pyo3.rs).
+//!
+//! This module is only available with the `python` feature.
+//! It defines a Python extension module named `jws` with the HMAC signers and verifier,
+//! and functions to sign and verify messages in the JWS Compact Serialization format.
+//!
+//! Build the extension module with `PYO3_BUILD_EXTENSION_MODULE=1 cargo rustc --release --lib --features python --crate-type cdylib`,
+//! and install the resulting `libjws.so` as `jws.so` (`jws.pyd` on Windows) somewhere on the Python path.
+//!
+//! Errors are raised as subclasses of `jws.JwsError`, one for each [`ErrorKind`].
+//!
+//! # Example:
+//! ```python
+//! import jws
+//!
+//! key = b"this is a secret key that is long enough for all HMAC algorithms"
+//! token = jws.encode_sign({"typ": "JWT"}, b"payload", jws.Hs256Signer(key))
+//!
+//! # Raises jws.InvalidSignatureError if the signature is invalid.
+//! message = jws.decode_verify(token, jws.HmacVerifier(key))
+//! assert message.payload == b"payload"
+//! ```
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use crate::compact;
+use crate::hmac::{HmacVerifier, Hs256Signer, Hs384Signer, Hs512Signer};
+use crate::{Error, ErrorKind, JsonObject};
+
+create_exception!(jws, JwsError, PyException, "Base class for all errors raised by the jws module.");
+create_exception!(jws, MissingHeaderParamError, JwsError, "A required header parameter is missing.");
+create_exception!(jws, InvalidHeaderParamError, JwsError, "A header parameter was found but its value is invalid.");
+create_exception!(jws, UnsupportedMacAlgorithmError, JwsError, "The MAC algorithm indicated by the message header is not supported.");
+create_exception!(jws, InvalidMessageError, JwsError, "The message being processed is not valid.");
+create_exception!(jws, InvalidSignatureError, JwsError, "The signature of the message being verified is invalid.");
+create_exception!(jws, UnsupportedEncryptionAlgorithmError, JwsError, "The encryption algorithm is not supported.");
+create_exception!(jws, DecryptionFailedError, JwsError, "The content of an encrypted message could not be decrypted.");
+create_exception!(jws, InvalidKeyError, JwsError, "A key is not suitable for the algorithm, for example because it is too short.");
+create_exception!(jws, OtherError, JwsError, "An error that doesn't match any of the other types.");
+
+impl From<Error> for PyErr {
+	fn from(error: Error) -> Self {
+		let message = error.to_string();
+		match error.kind() {
+			ErrorKind::MissingHeaderParam             => MissingHeaderParamError::new_err(message),
+			ErrorKind::InvalidHeaderParam             => InvalidHeaderParamError::new_err(message),
+			ErrorKind::UnsupportedMacAlgorithm        => UnsupportedMacAlgorithmError::new_err(message),
+			ErrorKind::InvalidMessage                 => InvalidMessageError::new_err(message),
+			ErrorKind::InvalidSignature               => InvalidSignatureError::new_err(message),
+			ErrorKind::UnsupportedEncryptionAlgorithm => UnsupportedEncryptionAlgorithmError::new_err(message),
+			ErrorKind::DecryptionFailed               => DecryptionFailedError::new_err(message),
+			ErrorKind::InvalidKey                     => InvalidKeyError::new_err(message),
+			ErrorKind::Other                          => OtherError::new_err(message),
+		}
+	}
+}
+
+/// Python wrapper for [`Hs256Signer`].
+#[pyclass(frozen, name = "Hs256Signer")]
+pub struct PyHs256Signer {
+	inner: Hs256Signer<Vec<u8>>,
+}
+
+/// Python wrapper for [`Hs384Signer`].
+#[pyclass(frozen, name = "Hs384Signer")]
+pub struct PyHs384Signer {
+	inner: Hs384Signer<Vec<u8>>,
+}
+
+/// Python wrapper for [`Hs512Signer`].
+#[pyclass(frozen, name = "Hs512Signer")]
+pub struct PyHs512Signer {
+	inner: Hs512Signer<Vec<u8>>,
+}
+
+/// Python wrapper for [`HmacVerifier`].
+#[pyclass(frozen, name = "HmacVerifier")]
+pub struct PyHmacVerifier {
+	inner: HmacVerifier<Vec<u8>>,
+}
+
+/// Python wrapper for [`compact::DecodedMessage`].
+#[pyclass(frozen, name = "DecodedMessage")]
+pub struct PyDecodedMessage {
+	/// The decoded header as dictionary.
+	#[pyo3(get)]
+	header: Py<PyAny>,
+
+	/// The decoded payload as bytes.
+	#[pyo3(get)]
+	payload: Py<PyBytes>,
+}
+
+#[pymethods]
+impl PyHs256Signer {
+	/// Create a new signer, raising `InvalidKeyError` if the key is too short.
+	#[new]
+	fn new(key: &[u8]) -> PyResult<Self> {
+		Ok(Self{inner: Hs256Signer::new(key.to_vec())?})
+	}
+}
+
+#[pymethods]
+impl PyHs384Signer {
+	/// Create a new signer, raising `InvalidKeyError` if the key is too short.
+	#[new]
+	fn new(key: &[u8]) -> PyResult<Self> {
+		Ok(Self{inner: Hs384Signer::new(key.to_vec())?})
+	}
+}
+
+#[pymethods]
+impl PyHs512Signer {
+	/// Create a new signer, raising `InvalidKeyError` if the key is too short.
+	#[new]
+	fn new(key: &[u8]) -> PyResult<Self> {
+		Ok(Self{inner: Hs512Signer::new(key.to_vec())?})
+	}
+}
+
+#[pymethods]
+impl PyHmacVerifier {
+	/// Create a new verifier, raising `InvalidKeyError` if the key is too short.
+	#[new]
+	fn new(key: &[u8]) -> PyResult<Self> {
+		Ok(Self{inner: HmacVerifier::new(key.to_vec())?})
+	}
+}
+
+/// One of the signer classes.
+#[derive(FromPyObject)]
+enum AnySigner<'py> {
+	Hs256(PyRef<'py, PyHs256Signer>),
+	Hs384(PyRef<'py, PyHs384Signer>),
+	Hs512(PyRef<'py, PyHs512Signer>),
+}
+
+/// Sign a payload and encode it using the JWS Compact Serialization format.
+///
+/// The header must be a dictionary that can be serialized as JSON, or `None`.
+#[pyfunction]
+#[pyo3(signature = (header, payload, signer))]
+fn encode_sign(py: Python<'_>, header: Option<Bound<'_, PyDict>>, payload: &[u8], signer: AnySigner<'_>) -> PyResult<String> {
+	let header : JsonObject = match header {
+		None         => JsonObject::new(),
+		Some(header) => {
+			let json : String = py.import("json")?.call_method1("dumps", (header,))?.extract()?;
+			serde_json::from_str(&json).map_err(|e| Error::invalid_header_param(format!("failed to parse header: {}", e)))?
+		},
+	};
+
+	let encoded = match signer {
+		AnySigner::Hs256(signer) => compact::encode_sign(header, payload, &signer.inner)?,
+		AnySigner::Hs384(signer) => compact::encode_sign(header, payload, &signer.inner)?,
+		AnySigner::Hs512(signer) => compact::encode_sign(header, payload, &signer.inner)?,
+	};
+	Ok(encoded.into_data())
+}
+
+/// Decode a message in the JWS Compact Serialization format and verify the signature.
+#[pyfunction]
+fn decode_verify(py: Python<'_>, data: &str, verifier: PyRef<'_, PyHmacVerifier>) -> PyResult<PyDecodedMessage> {
+	let message = compact::decode_verify(data.as_bytes(), &verifier.inner)?;
+	let header  = serde_json::to_string(&message.header).map_err(|e| Error::other(format!("failed to serialize header: {}", e)))?;
+	Ok(PyDecodedMessage {
+		header  : py.import("json")?.call_method1("loads", (header,))?.unbind(),
+		payload : PyBytes::new(py, &message.payload).unbind(),
+	})
+}
+
+/// The `jws` Python module.
+#[pymodule]
+#[pyo3(name = "jws")]
+fn jws_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+	let py = module.py();
+	module.add("JwsError", py.get_type::<JwsError>())?;
+	module.add("MissingHeaderParamError", py.get_type::<MissingHeaderParamError>())?;
+	module.add("InvalidHeaderParamError", py.get_type::<InvalidHeaderParamError>())?;
+	module.add("UnsupportedMacAlgorithmError", py.get_type::<UnsupportedMacAlgorithmError>())?;
+	module.add("InvalidMessageError", py.get_type::<InvalidMessageError>())?;
+	module.add("InvalidSignatureError", py.get_type::<InvalidSignatureError>())?;
+	module.add("UnsupportedEncryptionAlgorithmError", py.get_type::<UnsupportedEncryptionAlgorithmError>())?;
+	module.add("DecryptionFailedError", py.get_type::<DecryptionFailedError>())?;
+	module.add("InvalidKeyError", py.get_type::<InvalidKeyError>())?;
+	module.add("OtherError", py.get_type::<OtherError>())?;
+
+	module.add_class::<PyHs256Signer>()?;
+	module.add_class::<PyHs384Signer>()?;
+	module.add_class::<PyHs512Signer>()?;
+	module.add_class::<PyHmacVerifier>()?;
+	module.add_class::<PyDecodedMessage>()?;
+
+	module.add_function(wrap_pyfunction!(encode_sign, module)?)?;
+	module.add_function(wrap_pyfunction!(decode_verify, module)?)?;
+	Ok(())
+}
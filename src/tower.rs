@@ -0,0 +1,317 @@
+//! Middleware for [`tower`](https://docs.rs/tower) services to verify bearer tokens.
+//!
+//! The [`BearerAuthLayer`] extracts the token from the `Authorization: Bearer` header of each request
+//! and verifies it with [`compact::decode_verify_async`] using the configured [`AsyncVerifier`].
+//! Verification runs inside the response future, so verifiers that fetch keys asynchronously don't block the executor.
+//! Synchronous [`crate::Verifier`]s can be used by wrapping them in [`crate::Blocking`].
+//! They run directly on the executor, so they should not perform blocking I/O.
+//! The [`DecodedMessage`] is inserted into the request extensions before the request is passed to the inner service.
+//!
+//! Requests without a valid token are rejected with `401 Unauthorized` and a `WWW-Authenticate` header
+//! as described in [RFC 6750 section 3](https://tools.ietf.org/html/rfc6750#section-3).
+//! The inner service is not called for rejected requests.
+//!
+//! This module is only available with the `tower` feature.
+//! It works with any framework built on `tower` and the `http` crate, like `axum` and `hyper`.
+//!
+//! # Example:
+//! ```
+//! use jws::compact::DecodedMessage;
+//! use jws::Blocking;
+//! use jws::hmac::HmacVerifier;
+//! use jws::tower::BearerAuthLayer;
+//! use tower_layer::Layer;
+//!
+//! # fn wrap<S>(service: S) -> jws::Result<()> {
+//! let verifier = HmacVerifier::new(b"this is a secret key that is long enough for all HMAC algorithms".to_vec())?;
+//! let service = BearerAuthLayer::new(Blocking(verifier)).with_realm("example").layer(service);
+//!
+//! // The inner service can get the verified message from the request extensions:
+//! // request.extensions().get::<DecodedMessage>()
+//! # Ok(())
+//! # }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{HeaderValue, Request, Response, StatusCode};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::compact::{self, DecodedMessage};
+use crate::{AsyncVerifier, Error};
+
+/// A [`Layer`] that verifies bearer tokens with an [`AsyncVerifier`].
+#[derive(Debug)]
+pub struct BearerAuthLayer<V> {
+	verifier : Arc<V>,
+	realm    : Option<Arc<str>>,
+}
+
+/// A [`Service`] that verifies bearer tokens before passing requests to the inner service.
+///
+/// Created by a [`BearerAuthLayer`].
+/// The inner service must implement [`Clone`], since it is moved into the response future to be called after verification.
+#[derive(Debug)]
+pub struct BearerAuth<S, V> {
+	inner    : S,
+	verifier : Arc<V>,
+	realm    : Option<Arc<str>>,
+}
+
+/// The response future of a [`BearerAuth`] service.
+pub struct ResponseFuture<B, E> {
+	future: Pin<Box<dyn Future<Output = Result<Response<B>, E>> + Send>>,
+}
+
+impl<V> BearerAuthLayer<V> {
+	/// Create a new layer that verifies tokens with the given verifier.
+	pub fn new(verifier: V) -> Self {
+		Self{verifier: Arc::new(verifier), realm: None}
+	}
+
+	/// Set the realm to report in the `WWW-Authenticate` header of rejected requests.
+	pub fn with_realm(mut self, realm: impl Into<String>) -> Self {
+		self.realm = Some(realm.into().into());
+		self
+	}
+
+	pub fn verifier(&self) -> &V {
+		&self.verifier
+	}
+}
+
+impl<V> Clone for BearerAuthLayer<V> {
+	fn clone(&self) -> Self {
+		Self{verifier: self.verifier.clone(), realm: self.realm.clone()}
+	}
+}
+
+impl<S, V> Layer<S> for BearerAuthLayer<V> {
+	type Service = BearerAuth<S, V>;
+
+	fn layer(&self, inner: S) -> Self::Service {
+		BearerAuth{inner, verifier: self.verifier.clone(), realm: self.realm.clone()}
+	}
+}
+
+impl<S, V> BearerAuth<S, V> {
+	pub fn inner(&self) -> &S {
+		&self.inner
+	}
+
+	pub fn into_inner(self) -> S {
+		self.inner
+	}
+}
+
+impl<S: Clone, V> Clone for BearerAuth<S, V> {
+	fn clone(&self) -> Self {
+		Self{inner: self.inner.clone(), verifier: self.verifier.clone(), realm: self.realm.clone()}
+	}
+}
+
+impl<S, V, ReqBody, ResBody> Service<Request<ReqBody>> for BearerAuth<S, V>
+where
+	S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+	S::Future: Send,
+	V: AsyncVerifier + Send + Sync + 'static,
+	ReqBody: Send + 'static,
+	ResBody: Default + 'static,
+{
+	type Response = Response<ResBody>;
+	type Error    = S::Error;
+	type Future   = ResponseFuture<ResBody, S::Error>;
+
+	fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		self.inner.poll_ready(cx)
+	}
+
+	fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+		// The service that was polled ready is moved into the future, and replaced by a clone.
+		let clone = self.inner.clone();
+		let mut inner = std::mem::replace(&mut self.inner, clone);
+		let verifier = self.verifier.clone();
+		let realm = self.realm.clone();
+		let token = bearer_token(&request).map(String::from);
+
+		let future = async move {
+			match verify_token(token, verifier.as_ref()).await {
+				Ok(message) => {
+					request.extensions_mut().insert(message);
+					inner.call(request).await
+				},
+				Err(error) => Ok(unauthorized(realm.as_deref(), error.as_ref())),
+			}
+		};
+		ResponseFuture{future: Box::pin(future)}
+	}
+}
+
+impl<B, E> Future for ResponseFuture<B, E> {
+	type Output = Result<Response<B>, E>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		self.future.as_mut().poll(cx)
+	}
+}
+
+impl<B, E> std::fmt::Debug for ResponseFuture<B, E> {
+	fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+		formatter.debug_struct("ResponseFuture").finish_non_exhaustive()
+	}
+}
+
+/// Get the bearer token from the `Authorization` header of a request.
+///
+/// Returns `None` if the header is missing or does not use the `Bearer` scheme.
+pub fn bearer_token<B>(request: &Request<B>) -> Option<&str> {
+	let value = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+	let (scheme, token) = value.split_once(' ')?;
+	if scheme.eq_ignore_ascii_case("bearer") {
+		Some(token.trim())
+	} else {
+		None
+	}
+}
+
+/// Verify the bearer token of a request.
+///
+/// Returns `Err(None)` if the request has no bearer token.
+async fn verify_token(token: Option<String>, verifier: &impl AsyncVerifier) -> Result<DecodedMessage, Option<Error>> {
+	let token = token.ok_or(None)?;
+	compact::decode_verify_async(token.as_bytes(), verifier).await.map_err(Some)
+}
+
+/// Create a `401 Unauthorized` response with a `WWW-Authenticate` challenge.
+///
+/// The error description only contains the kind of the error, not the full error message.
+fn unauthorized<B: Default>(realm: Option<&str>, error: Option<&Error>) -> Response<B> {
+	let mut params = Vec::new();
+	if let Some(realm) = realm {
+		params.push(format!("realm=\"{}\"", escape(realm)));
+	}
+	if let Some(error) = error {
+		params.push(String::from("error=\"invalid_token\""));
+		params.push(format!("error_description=\"{}\"", error.kind()));
+	}
+
+	let challenge = if params.is_empty() {
+		String::from("Bearer")
+	} else {
+		format!("Bearer {}", params.join(", "))
+	};
+
+	let mut response = Response::new(B::default());
+	*response.status_mut() = StatusCode::UNAUTHORIZED;
+	let challenge = HeaderValue::from_str(&challenge).unwrap_or_else(|_| HeaderValue::from_static("Bearer"));
+	response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+	response
+}
+
+/// Escape a value for use in a quoted string.
+fn escape(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{json_object, Blocking, JsonObject, Verifier};
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+
+	use assert2::assert;
+	use std::convert::Infallible;
+	use std::future::Ready;
+
+	const KEY : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+
+	/// Service that responds with the payload of the verified message.
+	#[derive(Clone)]
+	struct EchoPayload;
+
+	impl Service<Request<()>> for EchoPayload {
+		type Response = Response<String>;
+		type Error    = Infallible;
+		type Future   = Ready<Result<Response<String>, Infallible>>;
+
+		fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+			Poll::Ready(Ok(()))
+		}
+
+		fn call(&mut self, request: Request<()>) -> Self::Future {
+			let message = request.extensions().get::<DecodedMessage>().expect("missing DecodedMessage");
+			std::future::ready(Ok(Response::new(String::from_utf8(message.payload.clone()).unwrap())))
+		}
+	}
+
+	/// Verifier that yields to the executor before verifying, like a verifier that fetches keys remotely.
+	struct RemoteKey;
+
+	impl AsyncVerifier for RemoteKey {
+		fn verify_async(&self, protected_header: Option<&JsonObject>, unprotected_header: Option<&JsonObject>, encoded_header: &[u8], encoded_payload: &[u8], signature: &[u8]) -> impl Future<Output = crate::Result<()>> + Send {
+			let result = HmacVerifier::new(KEY).unwrap().verify(protected_header, unprotected_header, encoded_header, encoded_payload, signature);
+			let mut pending = true;
+			std::future::poll_fn(move |cx| {
+				if std::mem::take(&mut pending) {
+					cx.waker().wake_by_ref();
+					Poll::Pending
+				} else {
+					Poll::Ready(result.clone())
+				}
+			})
+		}
+	}
+
+	fn call<V: AsyncVerifier + Send + Sync + 'static>(service: &mut BearerAuth<EchoPayload, V>, authorization: Option<&str>) -> Response<String> {
+		let mut request = Request::new(());
+		if let Some(authorization) = authorization {
+			request.headers_mut().insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+		}
+		futures::executor::block_on(service.call(request)).unwrap()
+	}
+
+	#[test]
+	fn test_bearer_auth() {
+		let token = compact::encode_sign(json_object!{}, b"foo", &Hs256Signer::new(KEY).unwrap()).unwrap();
+		let mut service = BearerAuthLayer::new(Blocking(HmacVerifier::new(KEY).unwrap())).with_realm("example").layer(EchoPayload);
+
+		let response = call(&mut service, Some(&format!("Bearer {}", token.data())));
+		assert!(response.status() == StatusCode::OK);
+		assert!(response.body() == "foo");
+
+		let response = call(&mut service, Some(&format!("bearer {}", token.data())));
+		assert!(response.status() == StatusCode::OK);
+
+		let response = call(&mut service, None);
+		assert!(response.status() == StatusCode::UNAUTHORIZED);
+		assert!(response.headers()[WWW_AUTHENTICATE] == r#"Bearer realm="example""#);
+
+		let response = call(&mut service, Some(&format!("Basic {}", token.data())));
+		assert!(response.status() == StatusCode::UNAUTHORIZED);
+		assert!(response.headers()[WWW_AUTHENTICATE] == r#"Bearer realm="example""#);
+
+		let response = call(&mut service, Some(&format!("Bearer {}", token.data().replace(".Zm9v.", ".YmFy."))));
+		assert!(response.status() == StatusCode::UNAUTHORIZED);
+		assert!(response.headers()[WWW_AUTHENTICATE] == r#"Bearer realm="example", error="invalid_token", error_description="invalid signature""#);
+		assert!(response.body() == "");
+	}
+
+	#[test]
+	fn test_bearer_auth_async_verifier() {
+		let token = compact::encode_sign(json_object!{}, b"foo", &Hs256Signer::new(KEY).unwrap()).unwrap();
+		let mut service = BearerAuthLayer::new(RemoteKey).layer(EchoPayload);
+
+		let response = call(&mut service, Some(&format!("Bearer {}", token.data())));
+		assert!(response.status() == StatusCode::OK);
+		assert!(response.body() == "foo");
+
+		let response = call(&mut service, Some(&format!("Bearer {}", token.data().replace(".Zm9v.", ".YmFy."))));
+		assert!(response.status() == StatusCode::UNAUTHORIZED);
+		assert!(response.headers()[WWW_AUTHENTICATE] == r#"Bearer error="invalid_token", error_description="invalid signature""#);
+	}
+}
@@ -0,0 +1,128 @@
+//! Instrumentation with the [`tracing`](https://docs.rs/tracing) crate.
+//!
+//! This module is only compiled with the `tracing` feature.
+//! Events record the algorithm, the key ID and the error kind, but never payloads, signatures or keys.
+
+use crate::{Error, get_header_param, JsonObject};
+
+fn param<'a>(protected: Option<&'a JsonObject>, unprotected: Option<&'a JsonObject>, name: &str) -> Option<&'a str> {
+	get_header_param(protected, unprotected, name).and_then(|value| value.as_str())
+}
+
+/// Emit an event for a message that was signed, or failed to be.
+pub(crate) fn signed(header: &JsonObject, result: Result<(), &Error>) {
+	let alg = param(Some(header), None, "alg");
+	let kid = param(Some(header), None, "kid");
+	match result {
+		Ok(())     => tracing::debug!(alg, kid, "message signed"),
+		Err(error) => tracing::debug!(alg, kid, error = ?error.kind(), "failed to sign message"),
+	}
+}
+
+/// Emit an event for a message that was verified, or failed to be.
+///
+/// The header is `None` if the message could not be decoded.
+pub(crate) fn verified(header: Option<&JsonObject>, result: Result<(), &Error>) {
+	let alg = param(header, None, "alg");
+	let kid = param(header, None, "kid");
+	match result {
+		Ok(())     => tracing::debug!(alg, kid, "message verified"),
+		Err(error) => tracing::debug!(alg, kid, error = ?error.kind(), part = error.part().map(tracing::field::display), "message rejected"),
+	}
+}
+
+/// Emit an event for the outcome of a single verifier.
+///
+/// The `key` field records the label of the verifier, if it has one.
+pub(crate) fn verifier_outcome(verifier: &'static str, key: Option<&str>, protected: Option<&JsonObject>, unprotected: Option<&JsonObject>, result: Result<(), &Error>) {
+	let alg = param(protected, unprotected, "alg");
+	let kid = param(protected, unprotected, "kid");
+	match result {
+		Ok(())     => tracing::trace!(verifier, key, alg, kid, "signature accepted"),
+		Err(error) => tracing::trace!(verifier, key, alg, kid, error = ?error.kind(), "signature rejected"),
+	}
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+	use crate::{compact, json_object, Verifier};
+	use crate::hmac::{HmacVerifier, Hs256Signer};
+
+	use assert2::assert;
+	use std::fmt::Write;
+	use std::sync::{Arc, Mutex};
+	use tracing::field::{Field, Visit};
+	use tracing::span;
+
+	const KEY : &[u8] = b"this is a secret key that is long enough for all HMAC algorithms";
+
+	/// Subscriber that records span names and formatted events.
+	#[derive(Clone, Default)]
+	struct Recorder {
+		lines: Arc<Mutex<Vec<String>>>,
+	}
+
+	struct Fields<'a>(&'a mut String);
+
+	impl Visit for Fields<'_> {
+		fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+			write!(self.0, " {}={:?}", field.name(), value).unwrap();
+		}
+	}
+
+	impl tracing::Subscriber for Recorder {
+		fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+			true
+		}
+
+		fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+			self.lines.lock().unwrap().push(format!("span {}", span.metadata().name()));
+			span::Id::from_u64(1)
+		}
+
+		fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+		fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+		fn event(&self, event: &tracing::Event<'_>) {
+			let mut line = String::from("event");
+			event.record(&mut Fields(&mut line));
+			self.lines.lock().unwrap().push(line);
+		}
+
+		fn enter(&self, _span: &span::Id) {}
+
+		fn exit(&self, _span: &span::Id) {}
+	}
+
+	#[test]
+	fn test_tracing_events() {
+		let recorder = Recorder::default();
+		tracing::subscriber::with_default(recorder.clone(), || {
+			let signed = compact::encode_sign(json_object!{"kid": "key-1"}, b"secret payload", &Hs256Signer::new(KEY).unwrap()).unwrap();
+			let wrong = HmacVerifier::new(b"this is not the key, even though it is long enough for HMAC-SHA512").unwrap();
+			let right = HmacVerifier::new(KEY).unwrap().with_label("key-1");
+			assert!(let Ok(_) = compact::decode_verify(signed.as_bytes(), &wrong.or(right)));
+			assert!(let Err(_) = compact::decode_verify(b"not a token", &HmacVerifier::new(KEY).unwrap()));
+			assert!(let Ok(_) = futures::executor::block_on(compact::decode_verify_async(signed.as_bytes(), &crate::Blocking(HmacVerifier::new(KEY).unwrap()))));
+		});
+
+		let lines = recorder.lines.lock().unwrap();
+		assert!(lines[0] == "span encode_sign");
+		assert!(lines[1] == r#"event message=message signed alg="HS256" kid="key-1""#);
+		assert!(lines[2] == "span decode_verify");
+		assert!(lines[3] == "span or_verifier");
+		assert!(lines[4] == r#"event message=signature rejected verifier="HmacVerifier" alg="HS256" kid="key-1" error=InvalidSignature"#);
+		assert!(lines[5] == r#"event message=signature accepted verifier="HmacVerifier" key="key-1" alg="HS256" kid="key-1""#);
+		assert!(lines[6] == r#"event message=message verified alg="HS256" kid="key-1""#);
+		assert!(lines[7] == "span decode_verify");
+		assert!(lines[8] == "event message=message rejected error=InvalidMessage");
+		assert!(lines[9] == "span decode_verify_async");
+		assert!(lines[10] == r#"event message=signature accepted verifier="HmacVerifier" alg="HS256" kid="key-1""#);
+		assert!(lines[11] == r#"event message=message verified alg="HS256" kid="key-1""#);
+		assert!(lines.len() == 12);
+
+		// Payloads and keys are never recorded.
+		assert!(!lines.iter().any(|line| line.contains("secret")));
+	}
+}
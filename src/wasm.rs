@@ -0,0 +1,193 @@
+//! JavaScript bindings for WebAssembly using [`wasm-bindgen`](https://docs.rs/wasm-bindgen).
+//!
+//! This module is only available with the `wasm-bindgen` feature.
+//! It exports functions to sign, verify and decode messages in the JWS Compact Serialization format.
+//!
+//! Keys are passed as JSON Web Key objects, and verification also accepts a JSON Web Key Set.
+//! Like the [`crate::jwk`] module, only symmetric keys (key type `oct`) are currently supported.
+//!
+//! # Example:
+//! ```js
+//! import { sign, verify, decode } from "jws";
+//!
+//! const key = { kty: "oct", kid: "key-1", alg: "HS256", k: "..." };
+//! const token = sign({ typ: "JWT" }, new TextEncoder().encode("payload"), key);
+//!
+//! // Throws if the signature is invalid.
+//! const { header, payload } = verify(token, { keys: [key] });
+//! ```
+
+use js_sys::{Object, Reflect, Uint8Array};
+use serde_derive::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::compact::{self, DecodedMessage};
+use crate::hmac::{Hs256Signer, Hs384Signer, Hs512Signer};
+use crate::jwk::{Jwk, JwkSet};
+use crate::{Error, JsonObject, JsonValue, Result};
+
+/// Sign a payload with a JWK and encode it using the JWS Compact Serialization format.
+///
+/// The algorithm is taken from the `alg` parameter of the key, and defaults to HS256.
+/// If the key has a key ID, it is added to the header as `kid` parameter.
+#[wasm_bindgen]
+pub fn sign(header: JsValue, payload: &[u8], key: JsValue) -> std::result::Result<String, JsError> {
+	let header : JsonObject = if header.is_undefined() || header.is_null() {
+		JsonObject::new()
+	} else {
+		from_js_value(header, "header")?
+	};
+	let key : Jwk = from_js_value(key, "key")?;
+	sign_with_jwk(header, payload, &key).map_err(js_error)
+}
+
+/// Decode a message and verify it with a JWK or a JWK set.
+///
+/// Returns an object with the decoded `header` and the `payload` as `Uint8Array`.
+/// Throws an error if the message is invalid or the signature can not be verified.
+#[wasm_bindgen]
+pub fn verify(token: &str, keys: JsValue) -> std::result::Result<JsValue, JsError> {
+	let keys : VerificationKeys = from_js_value(keys, "keys")?;
+	let message = verify_with_keys(token, &keys).map_err(js_error)?;
+	message_to_js(&message, None)
+}
+
+/// Decode a message without verifying the signature.
+///
+/// Returns an object with the decoded `header`, the `payload` and the `signature` as `Uint8Array`.
+#[wasm_bindgen]
+pub fn decode(token: &str) -> std::result::Result<JsValue, JsError> {
+	let (message, signature) = compact::decode_unverified(token.as_bytes()).map_err(js_error)?;
+	message_to_js(&message, Some(&signature))
+}
+
+/// A single JWK or a JWK set.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum VerificationKeys {
+	Set(JwkSet),
+	Key(Jwk),
+}
+
+fn sign_with_jwk(mut header: JsonObject, payload: &[u8], jwk: &Jwk) -> Result<String> {
+	if let Some(kid) = &jwk.kid {
+		header.entry("kid".to_string()).or_insert_with(|| JsonValue::from(kid.as_str()));
+	}
+
+	let key = jwk.symmetric_key()?;
+	let signed = match jwk.alg.as_deref().unwrap_or("HS256") {
+		"HS256" => compact::encode_sign(header, payload, &Hs256Signer::new(key)?)?,
+		"HS384" => compact::encode_sign(header, payload, &Hs384Signer::new(key)?)?,
+		"HS512" => compact::encode_sign(header, payload, &Hs512Signer::new(key)?)?,
+		alg     => return Err(Error::unsupported_mac_algorithm(alg.to_string())),
+	};
+	Ok(signed.into_data())
+}
+
+fn verify_with_keys(token: &str, keys: &VerificationKeys) -> Result<DecodedMessage> {
+	match keys {
+		VerificationKeys::Set(keys) => compact::decode_verify(token.as_bytes(), keys),
+		VerificationKeys::Key(key)  => compact::decode_verify(token.as_bytes(), key),
+	}
+}
+
+fn from_js_value<T: serde::de::DeserializeOwned>(value: JsValue, name: &str) -> std::result::Result<T, JsError> {
+	serde_wasm_bindgen::from_value(value).map_err(|e| JsError::new(&format!("invalid {}: {}", name, e)))
+}
+
+fn message_to_js(message: &DecodedMessage, signature: Option<&[u8]>) -> std::result::Result<JsValue, JsError> {
+	let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+	let header = serde::Serialize::serialize(&message.header, &serializer).map_err(|e| JsError::new(&e.to_string()))?;
+
+	let object = Object::new();
+	set_property(&object, "header", &header)?;
+	set_property(&object, "payload", &Uint8Array::from(message.payload.as_slice()))?;
+	if let Some(signature) = signature {
+		set_property(&object, "signature", &Uint8Array::from(signature))?;
+	}
+	Ok(object.into())
+}
+
+fn set_property(object: &Object, name: &str, value: &JsValue) -> std::result::Result<(), JsError> {
+	Reflect::set(object, &JsValue::from_str(name), value)
+		.map(|_| ())
+		.map_err(|_| JsError::new(&format!("failed to set {} property", name)))
+}
+
+fn js_error(error: Error) -> JsError {
+	JsError::new(&error.to_string())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::json_object;
+	use assert2::assert;
+
+	const KEY : &str = r#"{"kty": "oct", "kid": "key-1", "alg": "HS384", "k": "dGhpcyBpcyBhIHNlY3JldCBrZXkgdGhhdCBpcyBsb25nIGVub3VnaCBmb3IgYWxsIEhNQUMgYWxnb3JpdGhtcw"}"#;
+
+	#[test]
+	fn test_sign_verify() {
+		let key : Jwk = serde_json::from_str(KEY).unwrap();
+		let token = sign_with_jwk(json_object!{"typ": "JWT"}, b"foo", &key).unwrap();
+
+		let decoded = verify_with_keys(&token, &VerificationKeys::Key(key.clone())).unwrap();
+		assert!(decoded.header == json_object!{"alg": "HS384", "kid": "key-1", "typ": "JWT"});
+		assert!(decoded.payload == b"foo");
+
+		let keys = VerificationKeys::Set(JwkSet{keys: vec![key]});
+		assert!(let Ok(_) = verify_with_keys(&token, &keys));
+		assert!(let Err(Error { kind: Error::InvalidSignature, .. }) = verify_with_keys(&token.replace(".Zm9v.", ".YmFy."), &keys));
+	}
+
+	#[test]
+	fn test_parse_verification_keys() {
+		assert!(let Ok(VerificationKeys::Key(_)) = serde_json::from_str(KEY));
+		assert!(let Ok(VerificationKeys::Set(_)) = serde_json::from_str(&format!(r#"{{"keys": [{}]}}"#, KEY)));
+	}
+
+	#[test]
+	fn test_sign_unsupported_key() {
+		let key : Jwk = serde_json::from_str(r#"{"kty": "oct", "alg": "RS256", "k": ""}"#).unwrap();
+		assert!(let Err(Error { kind: Error::UnsupportedMacAlgorithm, .. }) = sign_with_jwk(json_object!{}, b"foo", &key));
+
+		let key : Jwk = serde_json::from_str(r#"{"kty": "oct", "k": "c2hvcnQ"}"#).unwrap();
+		assert!(let Err(Error { kind: Error::InvalidKey, .. }) = sign_with_jwk(json_object!{}, b"foo", &key));
+	}
+}
+
+// Run with a headless runtime, for example:
+// CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner cargo test --target wasm32-unknown-unknown --features wasm-bindgen --lib
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_test {
+	use super::*;
+	use wasm_bindgen_test::wasm_bindgen_test;
+
+	const KEY : &str = r#"{"kty": "oct", "kid": "key-1", "k": "dGhpcyBpcyBhIHNlY3JldCBrZXkgdGhhdCBpcyBsb25nIGVub3VnaCBmb3IgYWxsIEhNQUMgYWxnb3JpdGhtcw"}"#;
+
+	fn js_key() -> JsValue {
+		js_sys::JSON::parse(KEY).unwrap()
+	}
+
+	#[wasm_bindgen_test]
+	fn test_sign_verify_js() {
+		let token = sign(JsValue::UNDEFINED, b"foo", js_key()).unwrap();
+		let decoded = verify(&token, js_key()).unwrap();
+
+		let payload = Reflect::get(&decoded, &JsValue::from_str("payload")).unwrap();
+		assert_eq!(Uint8Array::new(&payload).to_vec(), b"foo");
+
+		let header = Reflect::get(&decoded, &JsValue::from_str("header")).unwrap();
+		assert_eq!(Reflect::get(&header, &JsValue::from_str("kid")).unwrap().as_string().as_deref(), Some("key-1"));
+
+		let decoded = decode(&token).unwrap();
+		assert!(Reflect::has(&decoded, &JsValue::from_str("signature")).unwrap());
+	}
+
+	#[wasm_bindgen_test]
+	fn test_verify_invalid_js() {
+		let token = sign(JsValue::UNDEFINED, b"foo", js_key()).unwrap();
+		assert!(verify(&token.replace(".Zm9v.", ".YmFy."), js_key()).is_err());
+		assert!(verify(&token, JsValue::from_str("not a key")).is_err());
+	}
+}
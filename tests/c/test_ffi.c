@@ -0,0 +1,98 @@
+/* Test program for the C API, run by tests/ffi.rs. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "jws.h"
+
+#define CHECK(condition) do { \
+	if (!(condition)) { \
+		const char * message = jws_last_error_message(); \
+		fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n", __FILE__, __LINE__, #condition, message ? message : "none"); \
+		exit(1); \
+	} \
+} while (0)
+
+static const char KEY[] = "this is a secret key that is long enough for all HMAC algorithms";
+
+/* Signed with HS256 by the Rust library: header {"typ": "JWT"}, payload "hello from rust". */
+static const char RUST_TOKEN[] = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.aGVsbG8gZnJvbSBydXN0.8icLTy-tyv_uw0O65zo29ygsVh3IhHcV2l66iACsufc";
+
+static JwsKey * new_key(const char * algorithm) {
+	JwsKey * key = NULL;
+	CHECK(jws_key_new_hmac(algorithm, (const uint8_t *) KEY, strlen(KEY), &key) == JWS_STATUS_OK);
+	CHECK(key != NULL);
+	return key;
+}
+
+static void test_verify_rust_token(void) {
+	JwsKey * key = new_key("HS256");
+
+	char * header = NULL;
+	uint8_t * payload = NULL;
+	size_t payload_len = 0;
+	CHECK(jws_verify_compact(key, RUST_TOKEN, &header, &payload, &payload_len) == JWS_STATUS_OK);
+	CHECK(strcmp(header, "{\"alg\":\"HS256\",\"typ\":\"JWT\"}") == 0);
+	CHECK(payload_len == strlen("hello from rust"));
+	CHECK(memcmp(payload, "hello from rust", payload_len) == 0);
+
+	jws_string_free(header);
+	jws_bytes_free(payload, payload_len);
+	jws_key_free(key);
+}
+
+static void test_sign_verify(void) {
+	JwsKey * key = new_key("HS512");
+
+	char * token = NULL;
+	CHECK(jws_sign_compact(key, "{\"kid\": \"legacy\"}", (const uint8_t *) "payload", 7, &token) == JWS_STATUS_OK);
+
+	char * header = NULL;
+	size_t payload_len = 0;
+	CHECK(jws_verify_compact(key, token, &header, NULL, &payload_len) == JWS_STATUS_OK);
+	CHECK(strcmp(header, "{\"alg\":\"HS512\",\"kid\":\"legacy\"}") == 0);
+	CHECK(payload_len == 7);
+	CHECK(jws_last_error_message() == NULL);
+
+	jws_string_free(header);
+	jws_string_free(token);
+	jws_key_free(key);
+}
+
+static void test_errors(void) {
+	JwsKey * key = new_key("HS256");
+
+	/* Replace the payload "hello from rust" with "hello from C++". */
+	char tampered[sizeof(RUST_TOKEN)];
+	strcpy(tampered, RUST_TOKEN);
+	memcpy(strchr(tampered, '.') + 1, "aGVsbG8gZnJvbSBDKys", 19);
+	memmove(strchr(tampered, '.') + 20, strrchr(RUST_TOKEN, '.'), strlen(strrchr(RUST_TOKEN, '.')) + 1);
+	CHECK(jws_verify_compact(key, tampered, NULL, NULL, NULL) == JWS_STATUS_INVALID_SIGNATURE);
+	CHECK(jws_last_error_message() != NULL);
+
+	/* The message can be decoded without verifying it. */
+	uint8_t * payload = NULL;
+	size_t payload_len = 0;
+	CHECK(jws_decode_unverified(tampered, NULL, &payload, &payload_len) == JWS_STATUS_OK);
+	CHECK(payload_len == 14 && memcmp(payload, "hello from C++", 14) == 0);
+	jws_bytes_free(payload, payload_len);
+
+	CHECK(jws_verify_compact(key, "not a token", NULL, NULL, NULL) == JWS_STATUS_INVALID_MESSAGE);
+	CHECK(jws_verify_compact(NULL, RUST_TOKEN, NULL, NULL, NULL) == JWS_STATUS_INVALID_ARGUMENT);
+
+	JwsKey * other = NULL;
+	CHECK(jws_key_new_hmac("HS256", (const uint8_t *) "short", 5, &other) == JWS_STATUS_INVALID_KEY);
+	CHECK(jws_key_new_hmac("ES256", (const uint8_t *) KEY, strlen(KEY), &other) == JWS_STATUS_UNSUPPORTED_MAC_ALGORITHM);
+	CHECK(other == NULL);
+
+	jws_key_free(key);
+}
+
+int main(void) {
+	test_verify_rust_token();
+	test_sign_verify();
+	test_errors();
+	printf("all C API tests passed\n");
+	return 0;
+}
@@ -0,0 +1,39 @@
+//! Build the C API as shared library, then compile and run the C test program against it.
+#![cfg(all(feature = "cdylib", unix))]
+
+use assert2::assert;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_c_program() {
+	let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+	let target_dir   = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+
+	// Use a separate target directory to avoid waiting for the lock held by the running cargo.
+	let status = Command::new(env!("CARGO"))
+		.current_dir(manifest_dir)
+		.args(["rustc", "--lib", "--features", "cdylib", "--crate-type", "cdylib", "--target-dir"])
+		.arg(&target_dir)
+		.status()
+		.expect("failed to run cargo");
+	assert!(status.success());
+
+	let lib_dir = target_dir.join("debug");
+	let program = target_dir.join("test_ffi");
+	let status = Command::new(std::env::var("CC").unwrap_or_else(|_| String::from("cc")))
+		.arg(manifest_dir.join("tests/c/test_ffi.c"))
+		.arg("-Wall")
+		.arg("-Werror")
+		.arg("-I").arg(manifest_dir.join("include"))
+		.arg("-L").arg(&lib_dir)
+		.arg("-ljws")
+		.arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+		.arg("-o").arg(&program)
+		.status()
+		.expect("failed to run C compiler");
+	assert!(status.success());
+
+	let status = Command::new(&program).status().expect("failed to run C test program");
+	assert!(status.success());
+}
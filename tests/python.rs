@@ -0,0 +1,35 @@
+//! Build the Python extension module, then run the Python tests against it.
+#![cfg(all(feature = "python", target_os = "linux"))]
+
+use assert2::assert;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_python_module() {
+	let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+	let target_dir   = Path::new(env!("CARGO_TARGET_TMPDIR")).join("python");
+
+	// Use a separate target directory to avoid waiting for the lock held by the running cargo.
+	let status = Command::new(env!("CARGO"))
+		.current_dir(manifest_dir)
+		.args(["rustc", "--lib", "--features", "python", "--crate-type", "cdylib", "--target-dir"])
+		.arg(&target_dir)
+		.env("PYO3_BUILD_EXTENSION_MODULE", "1")
+		.status()
+		.expect("failed to run cargo");
+	assert!(status.success());
+
+	// Python expects the extension module to be named after the module.
+	let module_dir = target_dir.join("module");
+	std::fs::create_dir_all(&module_dir).unwrap();
+	std::fs::copy(target_dir.join("debug/libjws.so"), module_dir.join("jws.so")).unwrap();
+
+	let python = std::env::var("PYO3_PYTHON").unwrap_or_else(|_| String::from("python3"));
+	let status = Command::new(python)
+		.arg(manifest_dir.join("tests/python/test_jws.py"))
+		.env("PYTHONPATH", &module_dir)
+		.status()
+		.expect("failed to run Python");
+	assert!(status.success());
+}
@@ -0,0 +1,52 @@
+"""Tests for the Python bindings, run by tests/python.rs."""
+
+import unittest
+
+import jws
+
+KEY = b"this is a secret key that is long enough for all HMAC algorithms"
+WRONG_KEY = b"this is not the key, even though it is long enough for HMAC-SHA512"
+
+# Signed with HS256 by the Rust library: header {"typ": "JWT"}, payload "hello from rust".
+RUST_TOKEN = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.aGVsbG8gZnJvbSBydXN0.8icLTy-tyv_uw0O65zo29ygsVh3IhHcV2l66iACsufc"
+
+
+class TestJws(unittest.TestCase):
+	def test_verify_rust_token(self):
+		message = jws.decode_verify(RUST_TOKEN, jws.HmacVerifier(KEY))
+		self.assertEqual(message.header, {"alg": "HS256", "typ": "JWT"})
+		self.assertEqual(message.payload, b"hello from rust")
+
+	def test_sign_verify(self):
+		for signer, alg in [(jws.Hs256Signer, "HS256"), (jws.Hs384Signer, "HS384"), (jws.Hs512Signer, "HS512")]:
+			token = jws.encode_sign({"typ": "JWT", "nested": {"list": [1, 2.5, None]}}, b"payload", signer(KEY))
+			message = jws.decode_verify(token, jws.HmacVerifier(KEY))
+			self.assertEqual(message.header, {"alg": alg, "typ": "JWT", "nested": {"list": [1, 2.5, None]}})
+			self.assertEqual(message.payload, b"payload")
+
+	def test_sign_without_header(self):
+		token = jws.encode_sign(None, b"", jws.Hs256Signer(KEY))
+		self.assertEqual(jws.decode_verify(token, jws.HmacVerifier(KEY)).header, {"alg": "HS256"})
+
+	def test_errors(self):
+		with self.assertRaises(jws.InvalidSignatureError):
+			jws.decode_verify(RUST_TOKEN, jws.HmacVerifier(WRONG_KEY))
+		with self.assertRaises(jws.InvalidMessageError):
+			jws.decode_verify("not a token", jws.HmacVerifier(KEY))
+		with self.assertRaises(jws.InvalidKeyError):
+			jws.Hs256Signer(b"short")
+		with self.assertRaises(jws.InvalidKeyError):
+			jws.HmacVerifier(b"short")
+		with self.assertRaises(TypeError):
+			jws.encode_sign({"key": object()}, b"payload", jws.Hs256Signer(KEY))
+		with self.assertRaises(TypeError):
+			jws.encode_sign({}, b"payload", jws.HmacVerifier(KEY))
+
+	def test_error_hierarchy(self):
+		self.assertTrue(issubclass(jws.JwsError, Exception))
+		for name in ["MissingHeaderParamError", "InvalidHeaderParamError", "UnsupportedMacAlgorithmError", "InvalidMessageError", "InvalidSignatureError", "UnsupportedEncryptionAlgorithmError", "DecryptionFailedError", "InvalidKeyError", "OtherError"]:
+			self.assertTrue(issubclass(getattr(jws, name), jws.JwsError))
+
+
+if __name__ == "__main__":
+	unittest.main()